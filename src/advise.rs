@@ -0,0 +1,186 @@
+use crate::{
+    result_sink::Hit,
+    rotation_info::{Face, Rotations},
+    texture_provider::TextureProvider,
+};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// A block face visible in the screenshot, relative to the formation (as if facing north).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct VisibleFace {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub face: Face,
+}
+
+impl FromStr for VisibleFace {
+    type Err = String;
+
+    /// Parse "x,y,z" or "x,y,z,face" (the face defaults to up).
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = text.split(',').map(str::trim).collect();
+        let coordinate = |part: &str| {
+            part.parse::<i32>()
+                .map_err(|_| format!("\"{part}\" is not a coordinate"))
+        };
+        match parts[..] {
+            [x, y, z] | [x, y, z, _] => Ok(VisibleFace {
+                x: coordinate(x)?,
+                y: coordinate(y)?,
+                z: coordinate(z)?,
+                face: match parts.get(3) {
+                    Some(face) => clap::ValueEnum::from_str(face, true)?,
+                    None => Face::Up,
+                },
+            }),
+            _ => Err(format!(
+                "\"{text}\" is not a position like \"1,0,-2\" or \"1,0,-2,north\""
+            )),
+        }
+    }
+}
+
+impl fmt::Display for VisibleFace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}, {} ({})", self.x, self.y, self.z, self.face)
+    }
+}
+
+/// How well reading a visible face would split the candidates.
+pub struct Split {
+    pub visible: VisibleFace,
+    /// Expected information gained by reading the face
+    pub bits: f64,
+    /// Candidates expected to remain after reading the face
+    pub expected_left: f64,
+    /// Candidates remaining in the worst case
+    pub most_left: usize,
+}
+
+/// The rotation a candidate would show on a visible face, as read from the screenshot.
+fn rotation_seen<T: TextureProvider>(textures: &T, hit: &Hit, visible: &VisibleFace) -> i32 {
+    let (dx, dz) = hit.facing.turn(visible.x, visible.z);
+    let face = visible.face.turned(hit.facing);
+    let rotation = textures.get_texture(hit.x + dx, hit.y + visible.y, hit.z + dz, face.modulo());
+    // Turn the rotation in the world back into the one seen facing north (see Placement::turned)
+    let quarter_turns = hit.facing.quarter_turns();
+    let seen = match visible.face {
+        Face::Up => Rotations::single(rotation).turned(-quarter_turns),
+        Face::Down => Rotations::single(rotation).turned(quarter_turns),
+        _ => Rotations::single(rotation),
+    };
+    seen.iter().next().unwrap()
+}
+
+/// Rank the visible faces by how much reading them would tell the candidates apart. The
+/// best one comes first.
+pub fn rank<T: TextureProvider>(
+    textures: &T,
+    candidates: &[Hit],
+    visible: &[VisibleFace],
+) -> Vec<Split> {
+    let total = candidates.len() as f64;
+    let mut splits: Vec<Split> = visible
+        .iter()
+        .map(|visible| {
+            let mut groups: HashMap<i32, usize> = HashMap::new();
+            for hit in candidates {
+                *groups
+                    .entry(rotation_seen(textures, hit, visible))
+                    .or_insert(0) += 1;
+            }
+            let bits = groups
+                .values()
+                .map(|&count| {
+                    let share = count as f64 / total;
+                    share * (1.0 / share).log2()
+                })
+                .sum();
+            Split {
+                visible: *visible,
+                bits,
+                expected_left: groups
+                    .values()
+                    .map(|&count| (count * count) as f64)
+                    .sum::<f64>()
+                    / total,
+                most_left: groups.values().copied().max().unwrap_or(0),
+            }
+        })
+        .collect();
+    splits.sort_by(|a, b| b.bits.total_cmp(&a.bits));
+    splits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        facing::Facing, placement::Placement, rotation_info::RotationInfo,
+        texture_provider::GradientTextures,
+    };
+
+    #[test]
+    fn parses_visible_faces() {
+        let face = |x, y, z, face| VisibleFace { x, y, z, face };
+        assert_eq!("1,0,-2".parse(), Ok(face(1, 0, -2, Face::Up)));
+        assert_eq!(" 1, 0 ,-2, North".parse(), Ok(face(1, 0, -2, Face::North)));
+        assert_eq!("0,1,0,down".parse(), Ok(face(0, 1, 0, Face::Down)));
+        assert!("1,0".parse::<VisibleFace>().is_err());
+        assert!("1,0,-2,up,2".parse::<VisibleFace>().is_err());
+        assert!("1,a,-2".parse::<VisibleFace>().is_err());
+        assert!("1,0,-2,sideways".parse::<VisibleFace>().is_err());
+    }
+
+    #[test]
+    fn seen_rotations_match_the_turned_formation() {
+        let textures = GradientTextures;
+        for facing in Facing::ALL {
+            for face in [Face::Up, Face::Down, Face::North, Face::East] {
+                for (x, z) in [(0, 0), (1, -2), (-3, 1)] {
+                    let visible = VisibleFace { x, y: 1, z, face };
+                    let hit = Hit::for_test(120, 64, -75, facing);
+                    // An entry with the rotation seen has to match the hit once placed
+                    let seen = rotation_seen(&textures, &hit, &visible);
+                    let entry = RotationInfo::new(x, 1, z, seen, face);
+                    let placed = Placement::new(&[entry]).turned(facing).entries[0];
+                    let rotation = textures.get_texture(
+                        hit.x + placed.x,
+                        hit.y + placed.y,
+                        hit.z + placed.z,
+                        placed.face.modulo(),
+                    );
+                    assert!(placed.rotation.allows(rotation), "{facing} {visible}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ranks_the_face_that_splits_best_first() {
+        let candidates: Vec<Hit> = (0..4)
+            .map(|x| Hit::for_test(x, 0, 0, Facing::North))
+            .collect();
+        let top = VisibleFace {
+            x: 0,
+            y: 0,
+            z: 0,
+            face: Face::Up,
+        };
+        let side = VisibleFace {
+            face: Face::North,
+            ..top
+        };
+        let splits = rank(&GradientTextures, &candidates, &[side, top]);
+        assert_eq!(splits[0].visible, top);
+        assert_eq!(splits[0].bits, 2.0);
+        assert_eq!(splits[0].expected_left, 1.0);
+        assert_eq!(splits[0].most_left, 1);
+        // The side only tells odd from even
+        assert_eq!(splits[1].visible, side);
+        assert_eq!(splits[1].bits, 1.0);
+        assert_eq!(splits[1].expected_left, 2.0);
+        assert_eq!(splits[1].most_left, 2);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    facing::Facing, placement::Placement, region::Rect, rotation_info::RotationInfo,
+    scoring::Scoring, texture_provider::TextureProvider,
+};
+
+/// Amount of random positions used to measure how often each rotation occurs
+const SAMPLES: u64 = 200_000;
+
+/// Bits of information the entries of one block carry together: 2 when they pin down the
+/// rotation, less when several rotations are allowed (see [`RotationInfo::allows_block`]).
+/// Fails if the entries contradict each other, so no rotation matches them all.
+pub fn information_bits(block: &[&RotationInfo]) -> Result<f64, String> {
+    let allowed = (0..4)
+        .filter(|&rotation| block.iter().all(|entry| entry.allows_block(rotation)))
+        .count();
+    if allowed == 0 {
+        let entry = block[0];
+        let faces: Vec<String> = block
+            .iter()
+            .map(|entry| format!("{} {}", entry.face, entry.rotation))
+            .collect();
+        return Err(format!(
+            "The entries of the block at {}, {}, {} contradict each other ({}), no rotation matches them all",
+            entry.x,
+            entry.y,
+            entry.z,
+            faces.join(", ")
+        ));
+    }
+    Ok((4.0 / allowed as f64).log2())
+}
+
+/// How often a provider shows each rotation on top/bottom faces (modulo 4), measured at
+/// random positions inside the bounds. Side faces show these modulo 2.
+pub struct RotationFrequencies {
+    pub top: [f64; 4],
+}
+
+impl RotationFrequencies {
+    pub fn measure<T: TextureProvider>(
+        textures: &T,
+        bounds: &Rect,
+        (y_min, y_max): (i32, i32),
+    ) -> Self {
+        let mut top = [0u64; 4];
+        // SplitMix64, so the positions are the same on every run
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let mut next = |min: i32, max: i32| {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            (min as i64 + (z % ((max as i64 - min as i64 + 1) as u64)) as i64) as i32
+        };
+        for _ in 0..SAMPLES {
+            let x = next(bounds.x_min, bounds.x_max);
+            let y = next(y_min, y_max);
+            let z = next(bounds.z_min, bounds.z_max);
+            top[textures.get_texture(x, y, z, 4) as usize] += 1;
+        }
+        Self {
+            top: top.map(|count| count as f64 / SAMPLES as f64),
+        }
+    }
+
+    /// Chance that all entries of one block match a random position.
+    pub fn match_chance(&self, block: &[&RotationInfo]) -> f64 {
+        (0..4)
+            .filter(|&rotation| block.iter().all(|entry| entry.allows_block(rotation)))
+            .map(|rotation| self.top[rotation as usize])
+            .sum()
+    }
+}
+
+/// Chance that a random position matches the formation (in one facing) with at most
+/// failed entries up to a score of `max_failures`.
+pub fn false_positive_chance(
+    entries: &[RotationInfo],
+    frequencies: &RotationFrequencies,
+    max_failures: f64,
+) -> f64 {
+    Scoring::with_rotation_chances(entries, &vec![1.0; entries.len()], frequencies.top)
+        .p_value(max_failures)
+}
+
+/// Turns (as the facing they turn north into) under which the formation matches itself
+/// (moved as a whole), limited to the ones between the facings the scan checks. A hit of
+/// such a formation can't tell these apart.
+pub fn symmetries(entries: &[RotationInfo], facings: &[Facing]) -> Vec<Facing> {
+    let placement = Placement::new(entries);
+    Facing::ALL[1..]
+        .iter()
+        .copied()
+        .filter(|turn| {
+            facings.iter().any(|facing| {
+                let turned = (facing.quarter_turns() + turn.quarter_turns()) % 4;
+                facings.contains(&Facing::ALL[turned as usize])
+            })
+        })
+        .filter(|&turn| matches_itself(entries, &placement.turned(turn).entries))
+        .collect()
+}
+
+/// Whether both formations have entries on the same faces of the same blocks (after moving
+/// them onto each other) and every pair of entries allows a common rotation.
+fn matches_itself(entries: &[RotationInfo], transformed: &[RotationInfo]) -> bool {
+    let min_corner = |entries: &[RotationInfo]| {
+        (
+            entries.iter().map(|entry| entry.x).min().unwrap(),
+            entries.iter().map(|entry| entry.z).min().unwrap(),
+        )
+    };
+    let ((x, z), (tx, tz)) = (min_corner(entries), min_corner(transformed));
+    entries.len() == transformed.len()
+        && entries.iter().all(|entry| {
+            transformed.iter().any(|other| {
+                other.x - tx == entry.x - x
+                    && other.y == entry.y
+                    && other.z - tz == entry.z - z
+                    && other.face == entry.face
+                    && entry.rotation.overlaps(other.rotation)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation_info::{Face, Rotations};
+
+    #[test]
+    fn finds_symmetries() {
+        let line = [
+            RotationInfo::new(0, 0, 0, 1, Face::Up),
+            RotationInfo::new(1, 0, 0, 2, Face::Up),
+        ];
+        assert!(symmetries(&line, &Facing::ALL).is_empty());
+
+        // An unknown rotation matches however it is turned
+        let vague = [RotationInfo {
+            rotation: Rotations::ANY,
+            ..RotationInfo::new(0, 0, 0, 0, Face::Up)
+        }];
+        assert_eq!(symmetries(&vague, &Facing::ALL).len(), 3);
+        // Only one facing is checked, so there is nothing to mix it up with
+        assert!(symmetries(&vague, &[Facing::East]).is_empty());
+
+        // Turned around, the blocks swap places and their top rotations turn by 2
+        let pair = [
+            RotationInfo::new(0, 0, 0, 0, Face::Up),
+            RotationInfo::new(1, 0, 0, 2, Face::Up),
+        ];
+        assert_eq!(symmetries(&pair, &Facing::ALL), vec![Facing::South]);
+        assert_eq!(
+            symmetries(&pair, &[Facing::East, Facing::West]),
+            vec![Facing::South]
+        );
+        assert!(symmetries(&pair, &[Facing::North, Facing::East]).is_empty());
+    }
+
+    #[test]
+    fn counts_information_per_block() {
+        let top = RotationInfo::new(0, 0, 0, 1, Face::Up);
+        let side = RotationInfo::new(0, 0, 0, 1, Face::North);
+        let unknown = RotationInfo {
+            rotation: Rotations::ANY,
+            ..top
+        };
+        assert_eq!(information_bits(&[&top]), Ok(2.0));
+        assert_eq!(information_bits(&[&side]), Ok(1.0));
+        assert_eq!(information_bits(&[&unknown]), Ok(0.0));
+        // The side only tells what the top already does
+        assert_eq!(information_bits(&[&top, &side]), Ok(2.0));
+        assert_eq!(information_bits(&[&unknown, &side]), Ok(1.0));
+        // An odd top can't have an even side
+        let even_side = RotationInfo::new(0, 0, 0, 0, Face::North);
+        assert!(information_bits(&[&top, &even_side]).is_err());
+    }
+
+    #[test]
+    fn match_chances_follow_the_frequencies() {
+        let frequencies = RotationFrequencies {
+            top: [0.4, 0.3, 0.2, 0.1],
+        };
+        let top = RotationInfo::new(0, 0, 0, 0, Face::Up);
+        let side = RotationInfo::new(0, 0, 0, 0, Face::North);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(frequencies.match_chance(&[&top]), 0.4));
+        // Sides show whether the top rotation is odd
+        assert!(close(frequencies.match_chance(&[&side]), 0.6));
+        assert!(close(frequencies.match_chance(&[&top, &side]), 0.4));
+        let odd_side = RotationInfo::new(0, 0, 0, 1, Face::North);
+        assert_eq!(frequencies.match_chance(&[&top, &odd_side]), 0.0);
+
+        // Both faces on one block match together, unlike on two blocks (0.4 * 0.6)
+        let block = [top, side];
+        assert!(close(false_positive_chance(&block, &frequencies, 0.0), 0.4));
+        // The side fails alone for top rotation 2, both fail for odd ones
+        assert!(close(false_positive_chance(&block, &frequencies, 1.0), 0.6));
+        assert!(close(false_positive_chance(&block, &frequencies, 2.0), 1.0));
+        let apart = [top, RotationInfo::new(1, 0, 0, 0, Face::North)];
+        assert!(close(
+            false_positive_chance(&apart, &frequencies, 0.0),
+            0.24
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::world::World;
+use cubiomes::finders::BiomeID;
+use libcubiomes_sys::{Generator, Range};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// Width and length of a tile in quarts (4x4 blocks)
+const TILE_QUARTS: i32 = 64;
+const TILE_LEN: usize = (TILE_QUARTS * TILE_QUARTS) as usize;
+/// Tiles a reader keeps for itself (512 KiB), enough for every Y layer of one tile.
+/// They are counted against the memory budget of the map.
+const READER_TILES: usize = 128;
+/// Stored for biome ids that don't fit into a byte (none are known)
+const NO_BIOME: u8 = u8::MAX;
+
+/// Position of a tile: X and Z in tiles, Y in quarts.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+struct TileKey {
+    x: i32,
+    z: i32,
+    quart_y: i32,
+}
+
+/// Tiles in memory and when they were last used.
+#[derive(Default)]
+struct Tiles {
+    by_key: HashMap<TileKey, (Arc<Vec<u8>>, u64)>,
+    by_last_use: BTreeMap<u64, TileKey>,
+    uses: u64,
+}
+
+impl Tiles {
+    fn get(&mut self, key: TileKey) -> Option<Arc<Vec<u8>>> {
+        self.uses += 1;
+        let (tile, last_use) = self.by_key.get_mut(&key)?;
+        self.by_last_use.remove(last_use);
+        self.by_last_use.insert(self.uses, key);
+        *last_use = self.uses;
+        Some(Arc::clone(tile))
+    }
+
+    /// Add a tile, dropping the least recently used ones to keep at most `max_tiles`.
+    fn insert(&mut self, key: TileKey, tile: Arc<Vec<u8>>, max_tiles: usize) -> Arc<Vec<u8>> {
+        // Another worker might have generated the same tile in the meantime
+        if let Some(tile) = self.get(key) {
+            return tile;
+        }
+        while self.by_key.len() >= max_tiles {
+            let Some((_, least_recent)) = self.by_last_use.pop_first() else {
+                break;
+            };
+            self.by_key.remove(&least_recent);
+        }
+        self.uses += 1;
+        self.by_key.insert(key, (Arc::clone(&tile), self.uses));
+        self.by_last_use.insert(self.uses, key);
+        tile
+    }
+}
+
+/// Biomes at quart resolution (one biome per 4x4x4 blocks), generated in tiles of
+/// 64x64 quarts and one quart high.
+///
+/// Tiles are shared between all workers. Once the memory budget (minus the tiles the
+/// readers keep) is used up, the least recently used tiles get dropped. If a directory is
+/// given, tiles are also saved there and loaded instead of generated on later scans of the
+/// same world.
+pub struct BiomeMap {
+    world: World,
+    /// Tiles of the budget that are left for the shared ones
+    max_tiles: AtomicUsize,
+    dir: Option<PathBuf>,
+    tiles: Mutex<Tiles>,
+}
+
+impl BiomeMap {
+    pub fn new(world: World, budget_mib: usize, dir: Option<PathBuf>) -> Self {
+        let dir = dir.map(|dir| {
+            dir.join(format!(
+                "{}_{}_{}",
+                world.seed, world.version, world.dimension
+            ))
+        });
+        Self {
+            world,
+            max_tiles: AtomicUsize::new(budget_mib * 1024 * 1024 / TILE_LEN),
+            dir,
+            tiles: Mutex::default(),
+        }
+    }
+
+    /// Quart Y that biomes are sampled at for a block Y.
+    fn quart_y(&self, y: i32) -> i32 {
+        if self.world.has_3d_biomes() {
+            y.div_euclid(4)
+        } else {
+            63 / 4
+        }
+    }
+
+    fn tile_path(&self, key: TileKey) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| {
+            dir.join(key.quart_y.to_string())
+                .join(format!("{}_{}.bin", key.x, key.z))
+        })
+    }
+
+    fn cached_tile(&self, key: TileKey) -> Option<Arc<Vec<u8>>> {
+        self.tiles.lock().unwrap().get(key)
+    }
+
+    fn insert_tile(&self, key: TileKey, tile: Vec<u8>) -> Arc<Vec<u8>> {
+        let max_tiles = self.max_tiles.load(Ordering::Relaxed).max(1);
+        self.tiles
+            .lock()
+            .unwrap()
+            .insert(key, Arc::new(tile), max_tiles)
+    }
+
+    fn load_tile(&self, key: TileKey) -> Option<Vec<u8>> {
+        let tile = std::fs::read(self.tile_path(key)?).ok()?;
+        (tile.len() == TILE_LEN).then_some(tile)
+    }
+
+    fn save_tile(&self, key: TileKey, tile: &[u8]) {
+        let Some(path) = self.tile_path(key) else {
+            return;
+        };
+        let tmp_path = path.with_extension("bin.tmp");
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| std::fs::write(&tmp_path, tile))
+            .and_then(|_| std::fs::rename(&tmp_path, &path));
+        if let Err(err) = result {
+            log::warn!("Failed to save biome tile to {path:?}: {err}");
+        }
+    }
+}
+
+/// A worker's handle to a [`BiomeMap`]. Generates missing tiles with its own generator.
+///
+/// The tiles used last are kept by the reader, so walking up a column (one tile every
+/// 4 blocks) doesn't need to lock the shared map.
+pub struct BiomeMapReader {
+    map: Arc<BiomeMap>,
+    generator: Box<Generator>,
+    local: Tiles,
+    /// The tile of the last lookup, which is most likely to be used again
+    last: Option<(TileKey, Arc<Vec<u8>>)>,
+    /// Tiles taken from the budget of the map, given back when dropped
+    reserved: usize,
+}
+
+impl BiomeMapReader {
+    pub fn new(map: Arc<BiomeMap>) -> Self {
+        let generator = map.world.generator();
+        // Take the tiles kept here from the budget of the shared ones
+        let max_tiles = map
+            .max_tiles
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |max_tiles| {
+                Some(max_tiles.saturating_sub(READER_TILES))
+            })
+            .unwrap();
+        Self {
+            map,
+            generator,
+            local: Tiles::default(),
+            last: None,
+            reserved: max_tiles.min(READER_TILES),
+        }
+    }
+
+    pub fn get_biome_at(&mut self, x: i32, y: i32, z: i32) -> Result<BiomeID, String> {
+        let (quart_x, quart_z) = (x.div_euclid(4), z.div_euclid(4));
+        let key = TileKey {
+            x: quart_x.div_euclid(TILE_QUARTS),
+            z: quart_z.div_euclid(TILE_QUARTS),
+            quart_y: self.map.quart_y(y),
+        };
+        let tile = match &self.last {
+            Some((last_key, tile)) if *last_key == key => tile,
+            _ => {
+                let tile = match self.local.get(key) {
+                    Some(tile) => tile,
+                    None => {
+                        let tile = self.tile(key)?;
+                        self.local.insert(key, tile, self.reserved.max(1))
+                    }
+                };
+                &self.last.insert((key, tile)).1
+            }
+        };
+        let index = quart_z.rem_euclid(TILE_QUARTS) * TILE_QUARTS + quart_x.rem_euclid(TILE_QUARTS);
+        Ok(match tile[index as usize] {
+            NO_BIOME => -1 as BiomeID,
+            id => id as BiomeID,
+        })
+    }
+
+    fn tile(&self, key: TileKey) -> Result<Arc<Vec<u8>>, String> {
+        if let Some(tile) = self.map.cached_tile(key) {
+            return Ok(tile);
+        }
+        if let Some(tile) = self.map.load_tile(key) {
+            return Ok(self.map.insert_tile(key, tile));
+        }
+        let tile = self.generate(key)?;
+        self.map.save_tile(key, &tile);
+        Ok(self.map.insert_tile(key, tile))
+    }
+
+    fn generate(&self, key: TileKey) -> Result<Vec<u8>, String> {
+        let start = Instant::now();
+        let range = Range {
+            scale: 4,
+            x: key.x * TILE_QUARTS,
+            z: key.z * TILE_QUARTS,
+            sx: TILE_QUARTS,
+            sz: TILE_QUARTS,
+            y: key.quart_y,
+            sy: 1,
+        };
+        let size = unsafe {
+            libcubiomes_sys::getMinCacheSize(&*self.generator, 4, TILE_QUARTS, 1, TILE_QUARTS)
+        };
+        let mut ids = vec![0; size.max(TILE_LEN)];
+        let err = unsafe { libcubiomes_sys::genBiomes(&*self.generator, ids.as_mut_ptr(), range) };
+        if err != 0 {
+            return Err(format!("Failed to generate biomes for {range:?}"));
+        }
+        log::trace!(
+            "Generated biome tile {}, {} at quart Y {} in {:?}",
+            key.x,
+            key.z,
+            key.quart_y,
+            start.elapsed()
+        );
+        Ok(ids[..TILE_LEN]
+            .iter()
+            .map(|&id| u8::try_from(id).unwrap_or(NO_BIOME))
+            .collect())
+    }
+}
+
+impl Drop for BiomeMapReader {
+    fn drop(&mut self) {
+        self.map
+            .max_tiles
+            .fetch_add(self.reserved, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Dimension, McVersion};
+
+    #[test]
+    fn drops_the_least_recently_used_tiles() {
+        let key = |x| TileKey {
+            x,
+            z: 0,
+            quart_y: 0,
+        };
+        let mut tiles = Tiles::default();
+        for x in 0..3 {
+            tiles.insert(key(x), Arc::new(vec![x as u8]), 3);
+        }
+        // Using the oldest tile keeps it around
+        assert!(tiles.get(key(0)).is_some());
+        tiles.insert(key(3), Arc::new(vec![3]), 3);
+        assert!(tiles.get(key(1)).is_none());
+        assert!(tiles.get(key(0)).is_some());
+        assert!(tiles.get(key(2)).is_some());
+        assert_eq!(*tiles.insert(key(2), Arc::new(vec![9]), 3), vec![2]);
+        assert_eq!(tiles.by_key.len(), 3);
+        assert_eq!(tiles.by_last_use.len(), 3);
+    }
+
+    #[test]
+    fn readers_take_their_tiles_from_the_budget() {
+        let world = World {
+            seed: 1,
+            version: McVersion::V1_19,
+            dimension: Dimension::Overworld,
+        };
+        let map = Arc::new(BiomeMap::new(world, 1, None));
+        let budget = 1024 * 1024 / TILE_LEN;
+        let first = BiomeMapReader::new(Arc::clone(&map));
+        assert_eq!(map.max_tiles.load(Ordering::Relaxed), budget - READER_TILES);
+        let second = BiomeMapReader::new(Arc::clone(&map));
+        let third = BiomeMapReader::new(Arc::clone(&map));
+        assert_eq!(map.max_tiles.load(Ordering::Relaxed), 0);
+        drop((first, second, third));
+        assert_eq!(map.max_tiles.load(Ordering::Relaxed), budget);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::world::McVersion::{self, *};
+use cubiomes::finders::BiomeID;
+use std::collections::HashSet;
+
+/// A biome as known to cubiomes. The same id can have multiple names, as some
+/// biomes got renamed over time.
+struct Biome {
+    id: i32,
+    name: &'static str,
+    /// First version the biome exists in with this name
+    since: McVersion,
+    /// Last version the biome exists in with this name
+    until: McVersion,
+}
+
+const fn biome(id: i32, name: &'static str, since: McVersion, until: McVersion) -> Biome {
+    Biome {
+        id,
+        name,
+        since,
+        until,
+    }
+}
+
+#[rustfmt::skip]
+const BIOMES: &[Biome] = &[
+    biome(0, "ocean", V1_7, V1_19),
+    biome(1, "plains", V1_7, V1_19),
+    biome(2, "desert", V1_7, V1_19),
+    biome(3, "mountains", V1_7, V1_17),
+    biome(3, "windswept_hills", V1_18, V1_19),
+    biome(4, "forest", V1_7, V1_19),
+    biome(5, "taiga", V1_7, V1_19),
+    biome(6, "swamp", V1_7, V1_19),
+    biome(7, "river", V1_7, V1_19),
+    biome(8, "nether", V1_7, V1_15),
+    biome(8, "nether_wastes", V1_16, V1_19),
+    biome(9, "the_end", V1_7, V1_19),
+    biome(10, "frozen_ocean", V1_7, V1_19),
+    biome(11, "frozen_river", V1_7, V1_19),
+    biome(12, "snowy_tundra", V1_7, V1_17),
+    biome(12, "snowy_plains", V1_18, V1_19),
+    biome(13, "snowy_mountains", V1_7, V1_17),
+    biome(14, "mushroom_fields", V1_7, V1_19),
+    biome(15, "mushroom_field_shore", V1_7, V1_17),
+    biome(16, "beach", V1_7, V1_19),
+    biome(17, "desert_hills", V1_7, V1_17),
+    biome(18, "wooded_hills", V1_7, V1_17),
+    biome(19, "taiga_hills", V1_7, V1_17),
+    biome(20, "mountain_edge", V1_7, V1_17),
+    biome(21, "jungle", V1_7, V1_19),
+    biome(22, "jungle_hills", V1_7, V1_17),
+    biome(23, "jungle_edge", V1_7, V1_17),
+    biome(23, "sparse_jungle", V1_18, V1_19),
+    biome(24, "deep_ocean", V1_7, V1_19),
+    biome(25, "stone_shore", V1_7, V1_17),
+    biome(25, "stony_shore", V1_18, V1_19),
+    biome(26, "snowy_beach", V1_7, V1_19),
+    biome(27, "birch_forest", V1_7, V1_19),
+    biome(28, "birch_forest_hills", V1_7, V1_17),
+    biome(29, "dark_forest", V1_7, V1_19),
+    biome(30, "snowy_taiga", V1_7, V1_19),
+    biome(31, "snowy_taiga_hills", V1_7, V1_17),
+    biome(32, "giant_tree_taiga", V1_7, V1_17),
+    biome(32, "old_growth_pine_taiga", V1_18, V1_19),
+    biome(33, "giant_tree_taiga_hills", V1_7, V1_17),
+    biome(34, "wooded_mountains", V1_7, V1_17),
+    biome(34, "windswept_forest", V1_18, V1_19),
+    biome(35, "savanna", V1_7, V1_19),
+    biome(36, "savanna_plateau", V1_7, V1_19),
+    biome(37, "badlands", V1_7, V1_19),
+    biome(38, "wooded_badlands_plateau", V1_7, V1_17),
+    biome(38, "wooded_badlands", V1_18, V1_19),
+    biome(39, "badlands_plateau", V1_7, V1_17),
+    biome(40, "small_end_islands", V1_9, V1_19),
+    biome(41, "end_midlands", V1_9, V1_19),
+    biome(42, "end_highlands", V1_9, V1_19),
+    biome(43, "end_barrens", V1_9, V1_19),
+    biome(44, "warm_ocean", V1_13, V1_19),
+    biome(45, "lukewarm_ocean", V1_13, V1_19),
+    biome(46, "cold_ocean", V1_13, V1_19),
+    biome(47, "deep_warm_ocean", V1_13, V1_17),
+    biome(48, "deep_lukewarm_ocean", V1_13, V1_19),
+    biome(49, "deep_cold_ocean", V1_13, V1_19),
+    biome(50, "deep_frozen_ocean", V1_13, V1_19),
+    biome(127, "the_void", V1_9, V1_19),
+    biome(129, "sunflower_plains", V1_7, V1_19),
+    biome(130, "desert_lakes", V1_7, V1_17),
+    biome(131, "gravelly_mountains", V1_7, V1_17),
+    biome(131, "windswept_gravelly_hills", V1_18, V1_19),
+    biome(132, "flower_forest", V1_7, V1_19),
+    biome(133, "taiga_mountains", V1_7, V1_17),
+    biome(134, "swamp_hills", V1_7, V1_17),
+    biome(140, "ice_spikes", V1_7, V1_19),
+    biome(149, "modified_jungle", V1_7, V1_17),
+    biome(151, "modified_jungle_edge", V1_7, V1_17),
+    biome(155, "tall_birch_forest", V1_7, V1_17),
+    biome(155, "old_growth_birch_forest", V1_18, V1_19),
+    biome(156, "tall_birch_hills", V1_7, V1_17),
+    biome(157, "dark_forest_hills", V1_7, V1_17),
+    biome(158, "snowy_taiga_mountains", V1_7, V1_17),
+    biome(160, "giant_spruce_taiga", V1_7, V1_17),
+    biome(160, "old_growth_spruce_taiga", V1_18, V1_19),
+    biome(161, "giant_spruce_taiga_hills", V1_7, V1_17),
+    biome(162, "modified_gravelly_mountains", V1_7, V1_17),
+    biome(163, "shattered_savanna", V1_7, V1_17),
+    biome(163, "windswept_savanna", V1_18, V1_19),
+    biome(164, "shattered_savanna_plateau", V1_7, V1_17),
+    biome(165, "eroded_badlands", V1_7, V1_19),
+    biome(166, "modified_wooded_badlands_plateau", V1_7, V1_17),
+    biome(167, "modified_badlands_plateau", V1_7, V1_17),
+    biome(168, "bamboo_jungle", V1_14, V1_19),
+    biome(169, "bamboo_jungle_hills", V1_14, V1_17),
+    biome(170, "soul_sand_valley", V1_16, V1_19),
+    biome(171, "crimson_forest", V1_16, V1_19),
+    biome(172, "warped_forest", V1_16, V1_19),
+    biome(173, "basalt_deltas", V1_16, V1_19),
+    biome(174, "dripstone_caves", V1_18, V1_19),
+    biome(175, "lush_caves", V1_18, V1_19),
+    biome(177, "meadow", V1_18, V1_19),
+    biome(178, "grove", V1_18, V1_19),
+    biome(179, "snowy_slopes", V1_18, V1_19),
+    biome(180, "jagged_peaks", V1_18, V1_19),
+    biome(181, "frozen_peaks", V1_18, V1_19),
+    biome(182, "stony_peaks", V1_18, V1_19),
+    biome(183, "deep_dark", V1_19, V1_19),
+    biome(184, "mangrove_swamp", V1_19, V1_19),
+];
+
+/// Predefined groups of biome ids. Ids that don't exist in a version are left out.
+#[rustfmt::skip]
+const GROUPS: &[(&str, &[i32])] = &[
+    ("oceans", &[0, 10, 24, 44, 45, 46, 47, 48, 49, 50]),
+    ("rivers", &[7, 11]),
+    ("beaches", &[16, 25, 26]),
+    ("taigas", &[5, 19, 30, 31, 32, 33, 133, 158, 160, 161]),
+    ("forests", &[4, 18, 27, 28, 29, 132, 155, 156, 157]),
+    ("jungles", &[21, 22, 23, 149, 151, 168, 169]),
+    ("swamps", &[6, 134, 184]),
+    ("savannas", &[35, 36, 163, 164]),
+    ("badlands", &[37, 38, 39, 165, 166, 167]),
+    ("mountains", &[3, 13, 20, 34, 131, 162, 178, 179, 180, 181, 182]),
+    ("caves", &[174, 175, 183]),
+    ("nether", &[8, 170, 171, 172, 173]),
+    ("end", &[9, 40, 41, 42, 43]),
+];
+
+fn exists_in(biome: &Biome, version: McVersion) -> bool {
+    (biome.since..=biome.until).contains(&version)
+}
+
+/// Ids of all biomes of the given version.
+pub fn all(version: McVersion) -> HashSet<BiomeID> {
+    BIOMES
+        .iter()
+        .filter(|biome| exists_in(biome, version))
+        .map(|biome| biome.id as BiomeID)
+        .collect()
+}
+
+/// Name of a biome id in the given version.
+pub fn name_of(id: BiomeID, version: McVersion) -> Option<&'static str> {
+    BIOMES
+        .iter()
+        .find(|biome| biome.id as BiomeID == id && exists_in(biome, version))
+        .map(|biome| biome.name)
+}
+
+/// Resolve a biome name (e.g. "dark_forest") or group (e.g. "#oceans") to biome ids.
+pub fn resolve(name: &str, version: McVersion) -> Result<HashSet<BiomeID>, String> {
+    let existing = all(version);
+    if let Some(group) = name.strip_prefix('#') {
+        let Some((_, ids)) = GROUPS.iter().find(|(group_name, _)| *group_name == group) else {
+            let groups: Vec<_> = GROUPS.iter().map(|(name, _)| format!("#{name}")).collect();
+            return Err(format!(
+                "Unknown biome group \"{name}\". Known groups are {}",
+                groups.join(", ")
+            ));
+        };
+        let ids: HashSet<BiomeID> = ids
+            .iter()
+            .map(|&id| id as BiomeID)
+            .filter(|id| existing.contains(id))
+            .collect();
+        if ids.is_empty() {
+            return Err(format!(
+                "The biome group \"{name}\" has no biomes in {version}"
+            ));
+        }
+        return Ok(ids);
+    }
+
+    let name = name.to_lowercase();
+    let name = name.strip_prefix("minecraft:").unwrap_or(&name);
+    let mut matching = BIOMES.iter().filter(|biome| biome.name == name).peekable();
+    if matching.peek().is_none() {
+        return Err(format!("Unknown biome \"{name}\""));
+    }
+    let mut other_versions = vec![];
+    for biome in matching {
+        if exists_in(biome, version) {
+            return Ok(HashSet::from([biome.id as BiomeID]));
+        }
+        match name_of(biome.id as BiomeID, version) {
+            Some(current_name) => {
+                return Err(format!(
+                    "The biome \"{name}\" is called \"{current_name}\" in {version}"
+                ))
+            }
+            None => other_versions.push(format!("{} to {}", biome.since, biome.until)),
+        }
+    }
+    Err(format!(
+        "The biome \"{name}\" doesn't exist in {version} (only in {})",
+        other_versions.join(", ")
+    ))
+}
+
+/// Resolve a list of biome names and groups to biome ids.
+pub fn resolve_all(names: &[String], version: McVersion) -> Result<HashSet<BiomeID>, String> {
+    names.iter().try_fold(HashSet::new(), |mut ids, name| {
+        ids.extend(resolve(name, version)?);
+        Ok(ids)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_names_and_groups() {
+        assert_eq!(resolve("plains", V1_18), Ok(HashSet::from([1])));
+        assert_eq!(
+            resolve("minecraft:Dark_Forest", V1_18),
+            Ok(HashSet::from([29]))
+        );
+        // Renamed biomes point to their name in the version
+        assert!(resolve("mountains", V1_18)
+            .unwrap_err()
+            .contains("windswept_hills"));
+        assert!(resolve("deep_dark", V1_16).is_err());
+        assert!(resolve("nowhere", V1_18).is_err());
+
+        // Groups only contain the biomes of the version
+        assert_eq!(resolve("#swamps", V1_16), Ok(HashSet::from([6, 134])));
+        assert_eq!(resolve("#swamps", V1_19), Ok(HashSet::from([6, 184])));
+        assert!(resolve("#caves", V1_16).is_err());
+        assert!(resolve("#unknown", V1_18).is_err());
+    }
+}
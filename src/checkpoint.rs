@@ -0,0 +1,262 @@
+use crate::result_sink::{Hit, ResultSink};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Hash what decides the results of a scan, so a checkpoint can't be resumed with a
+/// different config by accident. Uses FNV-1a over the JSON of `identity`, as it needs to
+/// stay stable between builds. A collision would only let a checkpoint of another scan
+/// through, which is unlikely enough at 64 bits for a check against mistakes.
+pub fn config_hash(identity: &impl Serialize) -> u64 {
+    let json = serde_json::to_vec(identity).expect("The scan can be written as JSON");
+    json.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A set of integers stored as sorted, non-overlapping, inclusive ranges.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<(i64, i64)>", into = "Vec<(i64, i64)>")]
+pub struct RangeSet {
+    ranges: BTreeMap<i64, i64>,
+}
+
+impl RangeSet {
+    pub fn contains(&self, value: i64) -> bool {
+        self.ranges
+            .range(..=value)
+            .next_back()
+            .is_some_and(|(_, &end)| value <= end)
+    }
+
+    pub fn insert(&mut self, value: i64) {
+        if self.contains(value) {
+            return;
+        }
+        let (mut start, mut end) = (value, value);
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..value).next_back() {
+            if prev_end + 1 == value {
+                start = prev_start;
+            }
+        }
+        if let Some(next_end) = self.ranges.get(&(value + 1)).copied() {
+            self.ranges.remove(&(value + 1));
+            end = next_end;
+        }
+        self.ranges.insert(start, end);
+    }
+
+    /// Amount of values in the set.
+    pub fn len(&self) -> u64 {
+        self.ranges
+            .iter()
+            .map(|(start, end)| (end - start) as u64 + 1)
+            .sum()
+    }
+}
+
+impl From<Vec<(i64, i64)>> for RangeSet {
+    fn from(ranges: Vec<(i64, i64)>) -> Self {
+        Self {
+            ranges: ranges.into_iter().collect(),
+        }
+    }
+}
+
+impl From<RangeSet> for Vec<(i64, i64)> {
+    fn from(set: RangeSet) -> Self {
+        set.ranges.into_iter().collect()
+    }
+}
+
+/// Version of the checkpoint format. Checkpoints without a version stored X columns
+/// instead of tile indices in `completed`.
+pub const VERSION: u32 = 2;
+
+/// Everything needed to continue a scan where it stopped.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub config_hash: u64,
+    /// Indices of the tiles that were fully scanned
+    pub completed: RangeSet,
+    /// Tiles that were stopped in between, with the X column to continue at
+    #[serde(default)]
+    pub partial: BTreeMap<u64, i32>,
+    /// All hits reported so far
+    pub hits: Vec<Hit>,
+    /// The best matches so far when only keeping those (see --top). They are only
+    /// reported at the end of a scan.
+    #[serde(default)]
+    pub top_candidates: Vec<Hit>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl Checkpoint {
+    pub fn new(config_hash: u64) -> Self {
+        Self {
+            version: VERSION,
+            config_hash,
+            ..Default::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let checkpoint: Self = serde_json::from_str(&content)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if checkpoint.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "format version {} isn't supported (expected {VERSION})",
+                    checkpoint.version
+                ),
+            ));
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// A checkpoint that is updated by all workers and periodically saved to a file.
+pub struct SharedCheckpoint {
+    path: PathBuf,
+    checkpoint: Mutex<Checkpoint>,
+}
+
+impl SharedCheckpoint {
+    pub fn new(path: PathBuf, checkpoint: Checkpoint) -> Self {
+        Self {
+            path,
+            checkpoint: Mutex::new(checkpoint),
+        }
+    }
+
+    pub fn is_completed(&self, unit: i64) -> bool {
+        self.checkpoint.lock().unwrap().completed.contains(unit)
+    }
+
+    pub fn complete(&self, unit: i64) {
+        let mut checkpoint = self.checkpoint.lock().unwrap();
+        checkpoint.completed.insert(unit);
+        checkpoint.partial.remove(&(unit as u64));
+    }
+
+    /// Remember that a tile was only scanned up to (excluding) the given X column.
+    pub fn stopped_at(&self, tile: u64, x: i32) {
+        self.checkpoint.lock().unwrap().partial.insert(tile, x);
+    }
+
+    /// The X column to continue a tile at, if it was stopped before.
+    pub fn resume_x(&self, tile: u64) -> Option<i32> {
+        self.checkpoint.lock().unwrap().partial.get(&tile).copied()
+    }
+
+    /// Remember the current best matches when only keeping those.
+    pub fn set_top_candidates(&self, hits: Vec<Hit>) {
+        self.checkpoint.lock().unwrap().top_candidates = hits;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the checkpoint. A temporary file is renamed over the old one, so a crash
+    /// while saving never leaves a broken checkpoint behind.
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_string(&*self.checkpoint.lock().unwrap())?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Records all reported hits into the checkpoint.
+pub struct CheckpointSink(pub Arc<SharedCheckpoint>);
+
+impl ResultSink for CheckpointSink {
+    fn report(&mut self, hit: &Hit) -> io::Result<()> {
+        self.0.checkpoint.lock().unwrap().hits.push(hit.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_path::TempPath;
+
+    fn ranges(values: &[i64]) -> Vec<(i64, i64)> {
+        let mut set = RangeSet::default();
+        for &value in values {
+            set.insert(value);
+        }
+        set.into()
+    }
+
+    #[test]
+    fn inserts_merge_ranges() {
+        // Adjacent values extend a range on either side
+        assert_eq!(ranges(&[5, 6, 4]), vec![(4, 6)]);
+        // Values already in a range change nothing
+        assert_eq!(ranges(&[1, 2, 3, 2, 1]), vec![(1, 3)]);
+        // Values with a gap start a new range, until the gap is filled
+        assert_eq!(ranges(&[1, 3, 8]), vec![(1, 1), (3, 3), (8, 8)]);
+        assert_eq!(ranges(&[1, 3, 8, 2]), vec![(1, 3), (8, 8)]);
+        assert_eq!(ranges(&[-2, 0, -1]), vec![(-2, 0)]);
+
+        let set = RangeSet::from(ranges(&[1, 2, 3, 7]));
+        assert_eq!(set.len(), 4);
+        assert!(set.contains(3) && set.contains(7));
+        assert!(!set.contains(0) && !set.contains(4) && !set.contains(8));
+    }
+
+    #[test]
+    fn saves_and_loads_progress() {
+        let path = TempPath::new("json");
+        let shared = SharedCheckpoint::new(path.to_path_buf(), Checkpoint::new(42));
+        for tile in [0, 1, 2, 5] {
+            shared.complete(tile);
+        }
+        shared.stopped_at(3, -17);
+        shared.stopped_at(6, 100);
+        // Completing a tile forgets where it was stopped
+        shared.complete(6);
+        shared.save().unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.config_hash, 42);
+        assert_eq!(Vec::from(loaded.completed), vec![(0, 2), (5, 6)]);
+        assert_eq!(loaded.partial, BTreeMap::from([(3, -17)]));
+    }
+
+    #[test]
+    fn config_hash_stays_the_same_between_builds() {
+        assert_eq!(config_hash(&(1, "a")), 0x5d4e0cbd3073f8ef);
+        assert_ne!(config_hash(&(1, Some(2.5))), config_hash(&(1, None::<f64>)));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let path = TempPath::new("json");
+        // Written before tiles were numbered, `completed` holds X columns
+        std::fs::write(&path, r#"{"config_hash":1,"completed":[[-5,3]],"hits":[]}"#).unwrap();
+        let old = Checkpoint::load(&path);
+        std::fs::write(&path, serde_json::to_string(&Checkpoint::new(1)).unwrap()).unwrap();
+        let current = Checkpoint::load(&path);
+        assert_eq!(old.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(current.unwrap().version, VERSION);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{biomes, world::McVersion};
+use cubiomes::finders::BiomeID;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// Default tolerance (distance in RGB) when matching colors
+pub const DEFAULT_TOLERANCE: f64 = 8.0;
+
+/// Which tint of the biome a color was taken from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Tint {
+    Grass,
+    Foliage,
+}
+
+/// Colors of a biome. Swamps have two grass colors, picked by noise.
+struct BiomeTint {
+    id: i32,
+    grass: &'static [u32],
+    foliage: u32,
+}
+
+const fn tint(id: i32, grass: &'static [u32], foliage: u32) -> BiomeTint {
+    BiomeTint { id, grass, foliage }
+}
+
+const SWAMP_GRASS: &[u32] = &[0x4c763c, 0x6a7039];
+
+/// Grass and foliage colors as computed from the default resource pack's colormaps.
+#[rustfmt::skip]
+const TINTS: &[BiomeTint] = &[
+    tint(0, &[0x8eb971], 0x71a74d),
+    tint(1, &[0x91bd59], 0x77ab2f),
+    tint(2, &[0xbfb755], 0xaea42a),
+    tint(3, &[0x8ab689], 0x6da36b),
+    tint(4, &[0x79c05a], 0x59ae30),
+    tint(5, &[0x86b783], 0x68a464),
+    tint(6, SWAMP_GRASS, 0x6a7039),
+    tint(7, &[0x8eb971], 0x71a74d),
+    tint(8, &[0xbfb755], 0xaea42a),
+    tint(9, &[0x8eb971], 0x71a74d),
+    tint(10, &[0x80b497], 0x60a17b),
+    tint(11, &[0x80b497], 0x60a17b),
+    tint(12, &[0x80b497], 0x60a17b),
+    tint(13, &[0x80b497], 0x60a17b),
+    tint(14, &[0x55c93f], 0x2bbb0f),
+    tint(15, &[0x55c93f], 0x2bbb0f),
+    tint(16, &[0x91bd59], 0x77ab2f),
+    tint(17, &[0xbfb755], 0xaea42a),
+    tint(18, &[0x79c05a], 0x59ae30),
+    tint(19, &[0x86b783], 0x68a464),
+    tint(20, &[0x8ab689], 0x6da36b),
+    tint(21, &[0x59c93c], 0x30bb0b),
+    tint(22, &[0x59c93c], 0x30bb0b),
+    tint(23, &[0x64c73f], 0x3eb80f),
+    tint(24, &[0x8eb971], 0x71a74d),
+    tint(25, &[0x8ab689], 0x6da36b),
+    tint(26, &[0x83b593], 0x64a278),
+    tint(27, &[0x88bb67], 0x6ba941),
+    tint(28, &[0x88bb67], 0x6ba941),
+    tint(29, &[0x507a32], 0x59ae30),
+    tint(30, &[0x80b497], 0x60a17b),
+    tint(31, &[0x80b497], 0x60a17b),
+    tint(32, &[0x86b87f], 0x68a55f),
+    tint(33, &[0x86b87f], 0x68a55f),
+    tint(34, &[0x8ab689], 0x6da36b),
+    tint(35, &[0xbfb755], 0xaea42a),
+    tint(36, &[0xbfb755], 0xaea42a),
+    tint(37, &[0x90814d], 0x9e814d),
+    tint(38, &[0x90814d], 0x9e814d),
+    tint(39, &[0x90814d], 0x9e814d),
+    tint(40, &[0x8eb971], 0x71a74d),
+    tint(41, &[0x8eb971], 0x71a74d),
+    tint(42, &[0x8eb971], 0x71a74d),
+    tint(43, &[0x8eb971], 0x71a74d),
+    tint(44, &[0x8eb971], 0x71a74d),
+    tint(45, &[0x8eb971], 0x71a74d),
+    tint(46, &[0x8eb971], 0x71a74d),
+    tint(47, &[0x8eb971], 0x71a74d),
+    tint(48, &[0x8eb971], 0x71a74d),
+    tint(49, &[0x8eb971], 0x71a74d),
+    tint(50, &[0x8eb971], 0x71a74d),
+    tint(127, &[0x8eb971], 0x71a74d),
+    tint(129, &[0x91bd59], 0x77ab2f),
+    tint(130, &[0xbfb755], 0xaea42a),
+    tint(131, &[0x8ab689], 0x6da36b),
+    tint(132, &[0x79c05a], 0x59ae30),
+    tint(133, &[0x86b783], 0x68a464),
+    tint(134, SWAMP_GRASS, 0x6a7039),
+    tint(140, &[0x80b497], 0x60a17b),
+    tint(149, &[0x59c93c], 0x30bb0b),
+    tint(151, &[0x64c73f], 0x3eb80f),
+    tint(155, &[0x88bb67], 0x6ba941),
+    tint(156, &[0x88bb67], 0x6ba941),
+    tint(157, &[0x507a32], 0x59ae30),
+    tint(158, &[0x80b497], 0x60a17b),
+    tint(160, &[0x86b87f], 0x68a55f),
+    tint(161, &[0x86b87f], 0x68a55f),
+    tint(162, &[0x8ab689], 0x6da36b),
+    tint(163, &[0xbfb755], 0xaea42a),
+    tint(164, &[0xbfb755], 0xaea42a),
+    tint(165, &[0x90814d], 0x9e814d),
+    tint(166, &[0x90814d], 0x9e814d),
+    tint(167, &[0x90814d], 0x9e814d),
+    tint(168, &[0x59c93c], 0x30bb0b),
+    tint(169, &[0x59c93c], 0x30bb0b),
+    tint(170, &[0xbfb755], 0xaea42a),
+    tint(171, &[0xbfb755], 0xaea42a),
+    tint(172, &[0xbfb755], 0xaea42a),
+    tint(173, &[0xbfb755], 0xaea42a),
+    tint(174, &[0x91bd59], 0x77ab2f),
+    tint(175, &[0x91bd59], 0x77ab2f),
+    tint(177, &[0x83bb6d], 0x63a948),
+    tint(178, &[0x80b497], 0x60a17b),
+    tint(179, &[0x80b497], 0x60a17b),
+    tint(180, &[0x80b497], 0x60a17b),
+    tint(181, &[0x80b497], 0x60a17b),
+    tint(182, &[0x9abe4b], 0x82ac1e),
+    tint(183, &[0x91bd59], 0x77ab2f),
+    tint(184, SWAMP_GRASS, 0x8db127),
+];
+
+/// A color constraint as written in the config.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct ColorConstraint {
+    pub grass_color: Option<String>,
+    pub foliage_color: Option<String>,
+    /// Distance in RGB the colors may be off by
+    pub color_tolerance: Option<f64>,
+}
+
+impl ColorConstraint {
+    pub fn is_empty(&self) -> bool {
+        self.grass_color.is_none() && self.foliage_color.is_none()
+    }
+
+    /// Biomes matching all given colors, or None if no color is given.
+    pub fn biomes(&self, version: McVersion) -> Result<Option<HashSet<BiomeID>>, String> {
+        let tolerance = self.color_tolerance.unwrap_or(DEFAULT_TOLERANCE);
+        let mut result: Option<HashSet<BiomeID>> = None;
+        for (tint, color) in [
+            (Tint::Grass, &self.grass_color),
+            (Tint::Foliage, &self.foliage_color),
+        ] {
+            let Some(color) = color else {
+                continue;
+            };
+            let ids = biomes_with_tint(tint, parse_color(color)?, tolerance, version);
+            if ids.is_empty() {
+                return Err(format!(
+                    "No biome in {version} has the {tint:?} color {color}"
+                ));
+            }
+            result = Some(match result {
+                Some(result) => &result & &ids,
+                None => ids,
+            });
+        }
+        Ok(result)
+    }
+}
+
+/// Parse a color like "#79c05a".
+pub fn parse_color(color: &str) -> Result<[f64; 3], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let rgb = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("\"{color}\" is not a color like \"#79c05a\""))?;
+    Ok(to_rgb(rgb))
+}
+
+fn to_rgb(color: u32) -> [f64; 3] {
+    [
+        ((color >> 16) & 0xff) as f64,
+        ((color >> 8) & 0xff) as f64,
+        (color & 0xff) as f64,
+    ]
+}
+
+fn colors_of(tint: Tint, biome: &BiomeTint) -> Vec<[f64; 3]> {
+    match tint {
+        Tint::Grass => biome.grass.iter().map(|&color| to_rgb(color)).collect(),
+        Tint::Foliage => vec![to_rgb(biome.foliage)],
+    }
+}
+
+/// Radius of the columns the game averages tints over (5x5 with the default biome blend)
+const BLEND_RADIUS: i32 = 2;
+
+/// Share of the neighbouring biomes in the blended color of a block inside a biome.
+/// Biomes come in cells of 4x4 columns, so even at the corner of a cell the block's own
+/// biome covers (r + 1) x (r + 1) of the averaged columns. At a 5x5 blend that leaves
+/// 16/25 for the others.
+const MAX_BLEND: f64 = {
+    let own = if BLEND_RADIUS + 1 < 4 {
+        BLEND_RADIUS + 1
+    } else {
+        4
+    };
+    let window = 2 * BLEND_RADIUS + 1;
+    1.0 - (own * own) as f64 / (window * window) as f64
+};
+
+/// Distance of a color to the blend of `own` and `other`, where `other` has a weight of
+/// at most [`MAX_BLEND`].
+fn distance_to_blend(color: [f64; 3], own: [f64; 3], other: [f64; 3]) -> f64 {
+    let direction: Vec<f64> = (0..3).map(|i| other[i] - own[i]).collect();
+    let length_squared: f64 = direction.iter().map(|d| d * d).sum();
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        let dot: f64 = (0..3).map(|i| (color[i] - own[i]) * direction[i]).sum();
+        (dot / length_squared).clamp(0.0, MAX_BLEND)
+    };
+    (0..3)
+        .map(|i| (own[i] + t * direction[i] - color[i]).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Biomes that can produce a tint color.
+///
+/// The game blends the colors of nearby biomes, so near biome borders a block shows
+/// a mix of its own color and the ones of its neighbours. A biome is included if its
+/// own color, or a blend with the color of any other biome of the version, is within
+/// the tolerance.
+pub fn biomes_with_tint(
+    tint: Tint,
+    color: [f64; 3],
+    tolerance: f64,
+    version: McVersion,
+) -> HashSet<BiomeID> {
+    let existing = biomes::all(version);
+    let tints: Vec<&BiomeTint> = TINTS
+        .iter()
+        .filter(|biome| existing.contains(&(biome.id as BiomeID)))
+        .collect();
+    let all_colors: Vec<[f64; 3]> = tints
+        .iter()
+        .flat_map(|biome| colors_of(tint, biome))
+        .collect();
+    tints
+        .iter()
+        .filter(|biome| {
+            colors_of(tint, biome).into_iter().any(|own| {
+                all_colors
+                    .iter()
+                    .any(|&other| distance_to_blend(color, own, other) <= tolerance)
+            })
+        })
+        .map(|biome| biome.id as BiomeID)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::McVersion::V1_18;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#79c05a"), Ok([121.0, 192.0, 90.0]));
+        assert_eq!(parse_color("79C05A"), Ok([121.0, 192.0, 90.0]));
+        assert!(parse_color("#79c05").is_err());
+        assert!(parse_color("#79c05a0").is_err());
+        assert!(parse_color("#zzzzzz").is_err());
+    }
+
+    #[test]
+    fn finds_biomes_by_tint() {
+        let forest = to_rgb(0x79c05a);
+        let desert = to_rgb(0xbfb755);
+        let grass = |color, tolerance| biomes_with_tint(Tint::Grass, color, tolerance, V1_18);
+        let found = grass(forest, 1.0);
+        assert!(found.contains(&4) && found.contains(&132));
+        assert!(!found.contains(&2));
+        // Swamps have two grass colors
+        assert!(grass(to_rgb(0x6a7039), 1.0).contains(&6));
+        assert!(biomes_with_tint(Tint::Foliage, to_rgb(0x59ae30), 1.0, V1_18).contains(&29));
+
+        // At the corner of a forest cell, 16 of the 25 blended columns can be desert
+        let blend = |share: f64| -> [f64; 3] {
+            std::array::from_fn(|i| forest[i] + share * (desert[i] - forest[i]))
+        };
+        assert!(grass(blend(0.6), 1.0).contains(&4));
+        assert!(grass(blend(0.6), 1.0).contains(&2));
+    }
+}
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The horizontal direction the player was looking at when the screenshot was taken
+/// (as shown on the F3 screen).
+///
+/// A formation is written down as if the player was facing north: X goes to the right
+/// and Z towards the player. Every other facing turns the formation clockwise (viewed
+/// from above) by the given amount of quarter turns.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Facing {
+    pub const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    /// Clockwise quarter turns (viewed from above) compared to facing north.
+    pub const fn quarter_turns(self) -> i32 {
+        match self {
+            Facing::North => 0,
+            Facing::East => 1,
+            Facing::South => 2,
+            Facing::West => 3,
+        }
+    }
+
+    /// Turn a relative X/Z offset of the formation into world space.
+    pub const fn turn(self, x: i32, z: i32) -> (i32, i32) {
+        match self {
+            Facing::North => (x, z),
+            Facing::East => (-z, x),
+            Facing::South => (-x, -z),
+            Facing::West => (z, -x),
+        }
+    }
+}
+
+impl fmt::Display for Facing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Facing::North => "north",
+            Facing::East => "east",
+            Facing::South => "south",
+            Facing::West => "west",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_towards_the_facing() {
+        // The block in front of the player (-Z in the formation) and the one to the right
+        let expected = [
+            (Facing::North, (0, -1), (1, 0)),
+            (Facing::East, (1, 0), (0, 1)),
+            (Facing::South, (0, 1), (-1, 0)),
+            (Facing::West, (-1, 0), (0, -1)),
+        ];
+        for (facing, front, right) in expected {
+            assert_eq!(facing.turn(0, -1), front, "{facing}");
+            assert_eq!(facing.turn(1, 0), right, "{facing}");
+            // Turning doesn't move the origin or change distances
+            assert_eq!(facing.turn(0, 0), (0, 0));
+            let (x, z) = facing.turn(3, -2);
+            assert_eq!(x * x + z * z, 13);
+        }
+        // One quarter turn after another
+        for facing in Facing::ALL {
+            let (x, z) = Facing::East.turn(2, 5);
+            let next = Facing::ALL[(facing.quarter_turns() as usize + 1) % 4];
+            assert_eq!(facing.turn(x, z), next.turn(2, 5));
+        }
+    }
+}
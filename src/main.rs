@@ -1,22 +1,66 @@
+mod advise;
+mod analyze;
+mod biome_map;
+mod biomes;
+mod checkpoint;
+mod colors;
+mod facing;
 mod placement;
+mod progress;
+mod region;
+mod result_sink;
 mod rotation_info;
+mod scheduler;
+mod scoring;
+mod structures;
+mod surface;
+#[cfg(test)]
+mod temp_path;
 mod texture_finder;
 mod texture_provider;
+mod top_hits;
+mod world;
 
 use crate::{
-    rotation_info::RotationInfo,
+    biome_map::{BiomeMap, BiomeMapReader},
+    checkpoint::{Checkpoint, CheckpointSink, SharedCheckpoint},
+    colors::ColorConstraint,
+    facing::Facing,
+    progress::Progress,
+    region::{Area, Rect, Region, TILE_SAMPLES},
+    result_sink::{JsonLinesSink, ResultSink, Results},
+    rotation_info::{Face, RotationInfo},
+    scheduler::{ScanOrder, Scheduler},
+    structures::{NearStructure, StructureFilter},
+    surface::SurfaceFilter,
     texture_provider::{Sodium19Textures, SodiumTextures, TextureProvider, VanillaTextures},
+    top_hits::TopHits,
+    world::{Dimension, McVersion, World},
 };
 use clap::Parser;
-use serde::Deserialize;
-use std::{collections::HashSet, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-pub const LO_SEED: i64 = 64149200;
+const DEFAULT_TILE_SIZE: i32 = 256;
+const DEFAULT_BIOME_MAP_MIB: usize = 64;
+/// Width and length (in columns) of the area used to compare when to check biomes
+const BIOME_BENCHMARK_SIZE: i32 = 32;
 
 #[derive(Parser)]
 enum Command {
     Scan(ScanOpts),
     Verify(VerifyOpts),
+    Analyze(AnalyzeOpts),
+    Advise(AdviseOpts),
+    Refine(RefineOpts),
 }
 
 #[derive(Parser)]
@@ -25,17 +69,65 @@ struct ScanOpts {
     #[clap(long, short)]
     log_level: Option<String>,
 
-    ///// Optional path to a new file to write results additionally into
-    //#[clap(long, short)]
-    //output: Option<PathBuf>,
-    //
-    /// Allow up to the given amount of failures (scan will take longer!)
+    /// Optional path to a new file to write results additionally into. Can be given
+    /// multiple times. Files ending in ".csv" are written as CSV, everything else as JSON Lines
+    #[clap(long, short)]
+    output: Vec<PathBuf>,
+
+    /// Don't write results to stdout
+    #[clap(long)]
+    no_stdout: bool,
+
+    /// Allow failed entries up to the given score (scan will take longer!). Each failed
+    /// entry counts with its confidence (1 by default), so this is the number of failures
+    /// when no confidences are given
     #[clap(long, short = 'f')]
-    max_failures: Option<usize>,
+    max_failures: Option<f64>,
+
+    /// Only keep the given amount of best partial matches (lowest score) and report them
+    /// once the scan completes (a stopped scan keeps them in the checkpoint). Positions can
+    /// score up to --max-failures (default: all entries but the least confident one), but
+    /// once enough matches are found, only better ones are looked for
+    #[clap(long)]
+    top: Option<usize>,
+
+    /// Periodically save the progress into this file, so the scan can be continued with --resume.
+    /// Without this, the progress is saved next to the config
+    #[clap(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Only save the progress when the scan gets stopped, not periodically
+    #[clap(long, conflicts_with = "checkpoint")]
+    no_checkpoint: bool,
+
+    /// Continue a scan from the given checkpoint. Progress keeps getting saved into it
+    /// unless --checkpoint is given as well. Settings that don't change the results (e.g.
+    /// threads or the biome map) can differ from the scan that saved it
+    #[clap(long)]
+    resume: Option<PathBuf>,
+
+    /// Seconds between saving checkpoints
+    #[clap(long, default_value_t = 60)]
+    checkpoint_interval: u64,
+
+    /// Stop the scan after this many new results. Can't be combined with --top, which only
+    /// reports once the scan completes
+    #[clap(long, conflicts_with = "top")]
+    max_results: Option<u64>,
+
+    /// Stop the scan after this time (e.g. "30m" or "2h 30m")
+    #[clap(long)]
+    time_budget: Option<humantime::Duration>,
+
+    /// Seconds between progress reports
+    #[clap(long, default_value_t = 10)]
+    progress_interval: u64,
+
+    /// Don't time a small part of the area with the biome check before and after the
+    /// rotation check after the scan. Only done with biomes to filter for and a seed
+    #[clap(long)]
+    no_biome_check_benchmark: bool,
 
-    ///// Optional path to a new file to write results additionally into
-    //#[clap(long, short)]
-    //output: Option<PathBuf>,
     /// Path to the toml config which specifies scanning parameters. See config.toml.sample for the format
     config: PathBuf,
 }
@@ -49,24 +141,351 @@ struct VerifyOpts {
     y: i32,
     // Z block coordinate
     z: i32,
-    // Used for the side values of certain textures
-    #[clap(long, short = 's')]
+    /// The face the rotation is read from (side faces only have 2 values)
+    #[clap(long, short = 'f', value_enum, default_value = "up")]
+    face: Face,
+    /// Same as `--face north`, as all sides show the same values
+    #[clap(long, short = 's', conflicts_with = "face")]
     is_side: bool,
 }
 
+/// Estimate whether the formation can be unique in the search area of a config, before scanning.
+#[derive(Parser)]
+struct AnalyzeOpts {
+    /// Score of failed entries a scan would allow
+    #[clap(long, short = 'f')]
+    max_failures: Option<f64>,
+
+    /// Path to the toml config. See config.toml.sample for the format
+    config: PathBuf,
+}
+
+/// Rank faces visible in the screenshot by how well reading them would tell the hits of a
+/// scan apart.
+#[derive(Parser)]
+struct AdviseOpts {
+    /// Hits of a scan, as written to a JSON Lines or CSV output file
+    #[clap(long)]
+    hits: PathBuf,
+
+    /// A visible face relative to the formation, as "x,y,z" (top face) or "x,y,z,face".
+    /// Can be given multiple times
+    #[clap(long = "position", short = 'p', required = true)]
+    positions: Vec<advise::VisibleFace>,
+
+    /// Path to the toml config of the scan. See config.toml.sample for the format
+    config: PathBuf,
+}
+
+/// Check the hits of an earlier scan again against an updated formation (e.g. with more
+/// entries), in the facing they matched in, instead of scanning again.
+#[derive(Parser)]
+struct RefineOpts {
+    /// Logging level (e.g. DEBUG, INFO, WARN or ERROR). You can also use the env RUST_LOG instead.
+    #[clap(long, short)]
+    log_level: Option<String>,
+
+    /// Hits of the earlier scan, as written to a JSON Lines or CSV output file
+    #[clap(long)]
+    hits: PathBuf,
+
+    /// Optional path to a new file to write the remaining hits additionally into. Can be given
+    /// multiple times. Files ending in ".csv" are written as CSV, everything else as JSON Lines
+    #[clap(long, short)]
+    output: Vec<PathBuf>,
+
+    /// Don't write results to stdout
+    #[clap(long)]
+    no_stdout: bool,
+
+    /// Allow failed entries up to the given score, like a scan does
+    #[clap(long, short = 'f')]
+    max_failures: Option<f64>,
+
+    /// Path to the toml config with the updated formation. See config.toml.sample for the format
+    config: PathBuf,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 struct Config {
-    x_min: i32,
-    x_max: i32,
-    y_min: i32,
-    y_max: i32,
-    z_min: i32,
-    z_max: i32,
+    /// A box to scan. Can be combined with `regions`
+    x_min: Option<i32>,
+    x_max: Option<i32>,
+    /// Defaults to the build limits of the dimension
+    y_min: Option<i32>,
+    y_max: Option<i32>,
+    z_min: Option<i32>,
+    z_max: Option<i32>,
+    /// Boxes, circles, rings and polygons to scan
+    #[serde(default)]
+    regions: Vec<Region>,
+    /// Regions to leave out
+    #[serde(default)]
+    exclude: Vec<Region>,
+    /// World seed. Needed for everything biome related
+    seed: Option<i64>,
+    #[serde(default)]
+    mc_version: McVersion,
+    #[serde(default)]
+    dimension: Dimension,
     threads: i32,
     pin_threads_to_cores: bool,
     textures: String,
+    /// Raw biome ids to filter for. Combined with `biomes`
+    #[serde(default)]
     filter_for_biome_ids: HashSet<cubiomes::finders::BiomeID>,
-    formation: Vec<RotationInfo>,
+    /// Biome names (e.g. "dark_forest") or groups (e.g. "#taigas") to filter for
+    #[serde(default)]
+    biomes: Vec<String>,
+    /// Biome names or groups to leave out. Without any other biomes, all but
+    /// these are allowed
+    #[serde(default)]
+    exclude_biomes: Vec<String>,
+    /// Only allow biomes with these grass and foliage colors
+    #[serde(flatten)]
+    colors: ColorConstraint,
+    /// Memory for biome tiles shared by all workers in MiB (default 64)
+    biome_map_mib: Option<usize>,
+    /// Save generated biome tiles here and reuse them in later scans
+    biome_map_dir: Option<PathBuf>,
+    /// Check the biome only for positions that match the rotations
+    #[serde(default)]
+    lazy_biome_check: bool,
+    /// Only scan columns near any of these structures
+    #[serde(default)]
+    near_structures: Vec<NearStructure>,
+    /// Only scan this many blocks below and above the approximate surface height
+    surface_window: Option<i32>,
+    /// Width and length of the parts the area is split into (default 256)
+    tile_size: Option<i32>,
+    /// Scan likely parts of the area first
+    order: Option<ScanOrder>,
+    /// Only check the formation for this facing instead of all four
+    facing: Option<Facing>,
+    formation: Vec<FormationEntry>,
+}
+
+/// Everything that decides which hits a scan finds, to tell whether a checkpoint belongs
+/// to it. Leaves out how the scan runs (threads, biome map, order) and how the config is
+/// written.
+#[derive(Serialize)]
+struct ScanIdentity {
+    /// Entries with their resolved biomes (sorted) and confidence
+    formation: Vec<(RotationInfo, Option<Vec<cubiomes::finders::BiomeID>>, f64)>,
+    area: (Vec<Region>, Vec<Region>),
+    y_range: (i32, i32),
+    tile_size: i32,
+    seed: Option<i64>,
+    mc_version: McVersion,
+    dimension: Dimension,
+    textures: String,
+    /// Resolved from the biomes, excluded biomes and colors (sorted)
+    biomes: Vec<cubiomes::finders::BiomeID>,
+    near_structures: Vec<NearStructure>,
+    surface_window: Option<i32>,
+    facing: Option<Facing>,
+    max_failures: Option<f64>,
+    top: Option<usize>,
+}
+
+impl ScanIdentity {
+    /// Needs the config with its biomes resolved and a valid area.
+    fn new(
+        config: &Config,
+        entry_biomes: &[Option<HashSet<cubiomes::finders::BiomeID>>],
+        max_failures: Option<f64>,
+        top: Option<usize>,
+    ) -> Self {
+        let sorted = |ids: &HashSet<cubiomes::finders::BiomeID>| {
+            let mut ids: Vec<cubiomes::finders::BiomeID> = ids.iter().copied().collect();
+            ids.sort_unstable();
+            ids
+        };
+        let area = config.area().unwrap();
+        Self {
+            formation: config
+                .formation
+                .iter()
+                .zip(entry_biomes)
+                .zip(config.confidences())
+                .map(|((entry, biomes), confidence)| {
+                    (entry.rotation, biomes.as_ref().map(sorted), confidence)
+                })
+                .collect(),
+            area: (area.regions, area.exclude),
+            y_range: config.y_range(),
+            tile_size: config.tile_size.unwrap_or(DEFAULT_TILE_SIZE),
+            seed: config.seed,
+            mc_version: config.mc_version,
+            dimension: config.dimension,
+            textures: config.textures.clone(),
+            biomes: sorted(&config.filter_for_biome_ids),
+            near_structures: config.near_structures.clone(),
+            surface_window: config.surface_window,
+            facing: config.facing,
+            max_failures,
+            top,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct FormationEntry {
+    #[serde(flatten)]
+    rotation: RotationInfo,
+    /// Biome names or groups this block has to be in
+    #[serde(default)]
+    biomes: Vec<String>,
+    /// Grass and foliage colors of this block
+    #[serde(flatten)]
+    colors: ColorConstraint,
+    /// How sure the rotation is, between 0 and 1 (default 1)
+    confidence: Option<f64>,
+}
+
+impl Config {
+    /// The Y range to scan (inclusive), falling back to the build limits.
+    fn y_range(&self) -> (i32, i32) {
+        let (min, max) = world::build_limits(self.mc_version, self.dimension);
+        (self.y_min.unwrap_or(min), self.y_max.unwrap_or(max))
+    }
+
+    /// The combined area of the box and all regions.
+    fn area(&self) -> Result<Area, String> {
+        let mut regions = self.regions.clone();
+        match (self.x_min, self.x_max, self.z_min, self.z_max) {
+            (Some(x_min), Some(x_max), Some(z_min), Some(z_max)) => regions.push(Region::Box {
+                x_min,
+                x_max,
+                z_min,
+                z_max,
+            }),
+            (None, None, None, None) => {}
+            _ => return Err("x_min, x_max, z_min and z_max need to be set together".to_owned()),
+        }
+        if regions.is_empty() {
+            return Err("Set x_min, x_max, z_min and z_max or add some regions".to_owned());
+        }
+        for region in regions.iter().chain(&self.exclude) {
+            region.validate()?;
+        }
+        Ok(Area {
+            regions,
+            exclude: self.exclude.clone(),
+        })
+    }
+
+    /// The area if columns need to be checked against it.
+    fn area_filter(&self) -> Option<Area> {
+        // Checked before the scan starts
+        let area = self.area().unwrap();
+        (!area.is_rect()).then_some(area)
+    }
+
+    /// Resolve `biomes`, `exclude_biomes` and colors into `filter_for_biome_ids`.
+    fn resolve_biomes(&mut self) -> Result<(), String> {
+        let included = biomes::resolve_all(&self.biomes, self.mc_version)?;
+        let excluded = biomes::resolve_all(&self.exclude_biomes, self.mc_version)?;
+        let colored = self.colors.biomes(self.mc_version)?;
+        if excluded.is_empty() && included.is_empty() && colored.is_none() {
+            return Ok(());
+        }
+
+        let mut ids = &self.filter_for_biome_ids | &included;
+        if ids.is_empty() {
+            ids = biomes::all(self.mc_version);
+        }
+        ids.retain(|id| !excluded.contains(id));
+        if let Some(colored) = colored {
+            ids.retain(|id| colored.contains(id));
+        }
+        if ids.is_empty() {
+            return Err("No biomes are left after applying exclude_biomes and colors".to_owned());
+        }
+        self.filter_for_biome_ids = ids;
+        Ok(())
+    }
+
+    /// Biomes every formation entry has to be in.
+    fn entry_biomes(&self) -> Result<Vec<Option<HashSet<cubiomes::finders::BiomeID>>>, String> {
+        self.formation
+            .iter()
+            .map(|entry| {
+                if entry.biomes.is_empty() && entry.colors.is_empty() {
+                    return Ok(None);
+                }
+                let mut ids = if entry.biomes.is_empty() {
+                    biomes::all(self.mc_version)
+                } else {
+                    biomes::resolve_all(&entry.biomes, self.mc_version)?
+                };
+                if let Some(colored) = entry.colors.biomes(self.mc_version)? {
+                    ids.retain(|id| colored.contains(id));
+                }
+                if ids.is_empty() {
+                    return Err(format!(
+                        "No biome matches the biomes and colors of the entry at {}, {}, {}",
+                        entry.rotation.x, entry.rotation.y, entry.rotation.z
+                    ));
+                }
+                Ok(Some(ids))
+            })
+            .collect()
+    }
+
+    /// How confident the rotation of every formation entry is.
+    fn confidences(&self) -> Vec<f64> {
+        self.formation
+            .iter()
+            .map(|entry| entry.confidence.unwrap_or(1.0))
+            .collect()
+    }
+
+    /// The score of a position where every entry fails.
+    fn total_confidence(&self) -> f64 {
+        self.confidences().iter().sum()
+    }
+
+    /// The score allowed by default when keeping the best matches: all entries but the
+    /// least confident one may fail.
+    fn default_max_failures(&self) -> f64 {
+        let least = self.confidences().into_iter().fold(f64::INFINITY, f64::min);
+        self.total_confidence() - least
+    }
+
+    /// Whether any biomes need to be looked up during the scan.
+    fn needs_biomes(&self) -> bool {
+        !self.filter_for_biome_ids.is_empty()
+            || self
+                .formation
+                .iter()
+                .any(|entry| !entry.biomes.is_empty() || !entry.colors.is_empty())
+    }
+
+    fn biome_filter(&self) -> Option<HashSet<cubiomes::finders::BiomeID>> {
+        (!self.filter_for_biome_ids.is_empty()).then(|| self.filter_for_biome_ids.clone())
+    }
+
+    fn structure_filter(&self) -> Option<StructureFilter> {
+        if self.near_structures.is_empty() {
+            return None;
+        }
+        // Checked before the scan starts
+        Some(StructureFilter::new(self.world()?, &self.near_structures).unwrap())
+    }
+
+    fn surface_filter(&self) -> Option<SurfaceFilter> {
+        let window = self.surface_window?;
+        Some(SurfaceFilter::new(self.world()?, window))
+    }
+
+    fn world(&self) -> Option<World> {
+        self.seed.map(|seed| World {
+            seed,
+            version: self.mc_version,
+            dimension: self.dimension,
+        })
+    }
 }
 
 fn main() {
@@ -74,18 +493,433 @@ fn main() {
     match Command::parse() {
         Command::Scan(opts) => scan(opts),
         Command::Verify(opts) => verify(opts),
+        Command::Analyze(opts) => analyze(opts),
+        Command::Advise(opts) => advise(opts),
+        Command::Refine(opts) => refine(opts),
     }
 }
 
-fn verify(opts: VerifyOpts) {
-    let modulo = if opts.is_side { 2 } else { 4 };
+fn advise(opts: AdviseOpts) {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "INFO");
+    }
+    env_logger::builder().format_timestamp_millis().init();
+
+    let config = load_config(&opts.config);
+    let candidates = match result_sink::read_hits(&opts.hits) {
+        Ok(hits) => hits,
+        Err(err) => {
+            log::error!("Failed to read hits from {:?}: {err}", opts.hits);
+            std::process::exit(1);
+        }
+    };
+    if candidates.len() < 2 {
+        log::error!(
+            "There are {} hits, nothing to tell apart!",
+            candidates.len()
+        );
+        std::process::exit(1);
+    }
+    if candidates.iter().any(|hit| hit.provider != config.textures) {
+        log::warn!(
+            "Some hits were found with other textures than {}, which are used to tell them apart",
+            config.textures
+        );
+    }
+    let splits = match config.textures.as_str() {
+        "Sodium" => advise::rank(&SodiumTextures {}, &candidates, &opts.positions),
+        "Sodium19" => advise::rank(&Sodium19Textures {}, &candidates, &opts.positions),
+        "Vanilla" => advise::rank(&VanillaTextures {}, &candidates, &opts.positions),
+        _ => {
+            log::error!("Unknown textures {:?}!", config.textures);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Reading these faces would tell the {} hits apart best ({} textures):",
+        candidates.len(),
+        config.textures
+    );
+    for (rank, split) in splits.iter().enumerate() {
+        println!(
+            "  {}. {}: {:.2} bits, {:.1} hits left on average, at most {}",
+            rank + 1,
+            split.visible,
+            split.bits,
+            split.expected_left,
+            split.most_left
+        );
+    }
+}
+
+fn refine(opts: RefineOpts) {
+    if let Some(level) = &opts.log_level {
+        std::env::set_var("RUST_LOG", level);
+    } else if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "INFO");
+    }
+    env_logger::builder().format_timestamp_millis().init();
+
+    let mut config = load_config(&opts.config);
+    let max_failures = opts.max_failures.unwrap_or(0.0);
+    check_max_failures(&config, max_failures);
+    if let Err(err) = config.area() {
+        log::error!("Invalid search area: {err}");
+        std::process::exit(1);
+    }
+    let entry_biomes = resolve_biomes(&mut config);
+    check_column_filters(&config);
+    let candidates = match result_sink::read_hits(&opts.hits) {
+        Ok(hits) => hits,
+        Err(err) => {
+            log::error!("Failed to read hits from {:?}: {err}", opts.hits);
+            std::process::exit(1);
+        }
+    };
+    // Every candidate is checked in the facing it matched in, even if the config is limited
+    // to another one by now
+    if let Some(facing) = config.facing.take() {
+        let others = candidates.iter().filter(|hit| hit.facing != facing).count();
+        if others > 0 {
+            log::info!(
+                "{others} hits facing another way than {facing} are checked in their own facing"
+            );
+        }
+    }
+    let placements = placements(&config, entry_biomes);
+    if candidates.iter().any(|hit| hit.provider != config.textures) {
+        log::warn!(
+            "Some hits were found with other textures than {}, which are used to check them again",
+            config.textures
+        );
+    }
+
+    let mut sinks: Vec<Box<dyn ResultSink>> = vec![];
+    if !opts.no_stdout {
+        sinks.push(Box::new(JsonLinesSink::new(std::io::stdout())));
+    }
+    for path in &opts.output {
+        match result_sink::create_file_sink(path) {
+            Ok(sink) => sinks.push(sink),
+            Err(err) => {
+                log::error!("Failed to create output file {path:?}: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let results = Arc::new(Results::new(sinks, HashSet::new()));
+    let biome_map = match config.world() {
+        Some(world) if config.needs_biomes() => Some(Arc::new(BiomeMap::new(
+            world,
+            config.biome_map_mib.unwrap_or(DEFAULT_BIOME_MAP_MIB),
+            config.biome_map_dir.clone(),
+        ))),
+        _ => None,
+    };
+    let remaining = match config.textures.as_str() {
+        "Sodium" => recheck_hits(
+            SodiumTextures {},
+            &config,
+            &placements,
+            biome_map,
+            &results,
+            &candidates,
+            max_failures,
+        ),
+        "Sodium19" => recheck_hits(
+            Sodium19Textures {},
+            &config,
+            &placements,
+            biome_map,
+            &results,
+            &candidates,
+            max_failures,
+        ),
+        "Vanilla" => recheck_hits(
+            VanillaTextures {},
+            &config,
+            &placements,
+            biome_map,
+            &results,
+            &candidates,
+            max_failures,
+        ),
+        _ => {
+            log::error!("Unknown textures {:?}!", config.textures);
+            std::process::exit(1);
+        }
+    };
+    results.flush();
+    let remaining = match remaining {
+        Ok(remaining) => remaining,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+    };
+    log::info!(
+        "{remaining} of {} hits still match the formation",
+        candidates.len()
+    );
+}
+
+/// Read and parse the config. Exits when that fails.
+fn load_config(config_path: &Path) -> Config {
+    if !config_path.exists() || config_path.is_dir() {
+        log::error!(
+            "Failed to load config ({config_path:?}). The file doesn't exist or is not a directory!"
+        );
+        std::process::exit(1);
+    }
+
+    let config_content = std::fs::read_to_string(config_path).expect("Reading toml config failed");
+    let mut config: Config = toml::from_str(&config_content).expect("Parsing toml config failed");
+
+    // Fix rotation values
+    for entry in &mut config.formation {
+        entry.rotation.fix_rotation();
+    }
+    if config
+        .formation
+        .iter()
+        .all(|entry| entry.rotation.is_unknown())
+    {
+        log::error!("At least one entry of the formation needs a known rotation!");
+        std::process::exit(1);
+    }
+    if let Some(entry) = config.formation.iter().find(|entry| {
+        entry
+            .confidence
+            .is_some_and(|confidence| !(confidence > 0.0 && confidence <= 1.0))
+    }) {
+        log::error!(
+            "The confidence of the entry at {}, {}, {} has to be above 0 and at most 1!",
+            entry.rotation.x,
+            entry.rotation.y,
+            entry.rotation.z
+        );
+        std::process::exit(1);
+    }
+    if let Some(Err(err)) = config.order.as_ref().map(ScanOrder::check) {
+        log::error!("Invalid scan order: {err}");
+        std::process::exit(1);
+    }
+    config
+}
+
+/// Resolve the biome filter and the biomes of the formation entries. Exits when they are invalid.
+fn resolve_biomes(config: &mut Config) -> Vec<Option<HashSet<cubiomes::finders::BiomeID>>> {
+    if let Err(err) = config.resolve_biomes() {
+        log::error!("Invalid biome filter: {err}");
+        std::process::exit(1);
+    }
+    let entry_biomes = match config.entry_biomes() {
+        Ok(entry_biomes) => entry_biomes,
+        Err(err) => {
+            log::error!("Invalid biomes in the formation: {err}");
+            std::process::exit(1);
+        }
+    };
+    if config.seed.is_none() && config.needs_biomes() {
+        log::error!("Filtering for biomes needs the seed of the world!");
+        std::process::exit(1);
+    }
+    entry_biomes
+}
+
+/// Check the score of failed entries a scan allows. Exits when it can't be used.
+fn check_max_failures(config: &Config, max_failures: f64) {
+    if max_failures.is_nan() || max_failures < 0.0 {
+        log::error!("The allowed failures can't be negative!");
+        std::process::exit(1);
+    }
+    let total = config.total_confidence();
+    if max_failures >= total {
+        log::error!(
+            "You shouldn't allow failures up to the score of all entries failing ({total})!"
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Check the structure and surface filters. Exits when they can't be used.
+fn check_column_filters(config: &Config) {
+    if !config.near_structures.is_empty() {
+        let Some(world) = config.world() else {
+            log::error!("Filtering for structures needs the seed of the world!");
+            std::process::exit(1);
+        };
+        if config.dimension != Dimension::Overworld {
+            log::error!("Only overworld structures are supported!");
+            std::process::exit(1);
+        }
+        if let Some(near) = config.near_structures.iter().find(|near| near.distance < 0) {
+            log::error!(
+                "The distance to {} structures can't be negative!",
+                near.structure
+            );
+            std::process::exit(1);
+        }
+        if let Err(err) = StructureFilter::new(world, &config.near_structures) {
+            log::error!("Invalid structure filter: {err}");
+            std::process::exit(1);
+        }
+    }
+    if let Some(window) = config.surface_window {
+        if config.seed.is_none() {
+            log::error!("Filtering for the surface height needs the seed of the world!");
+            std::process::exit(1);
+        }
+        if config.dimension != Dimension::Overworld {
+            log::error!("The surface height can only be estimated in the overworld!");
+            std::process::exit(1);
+        }
+        if window < 0 {
+            log::error!("The surface window can't be negative!");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The formation turned for every facing that should be checked.
+fn placements(
+    config: &Config,
+    entry_biomes: Vec<Option<HashSet<cubiomes::finders::BiomeID>>>,
+) -> Vec<(Facing, placement::Placement)> {
+    let formation: Vec<RotationInfo> = config
+        .formation
+        .iter()
+        .map(|entry| entry.rotation)
+        .collect();
+    let placement = placement::Placement::new(&formation)
+        .with_entry_biomes(entry_biomes)
+        .with_entry_confidences(&config.confidences());
+    match config.facing {
+        Some(facing) => vec![facing],
+        None => Facing::ALL.to_vec(),
+    }
+    .into_iter()
+    .map(|facing| (facing, placement.turned(facing)))
+    .collect()
+}
+
+fn analyze(opts: AnalyzeOpts) {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "INFO");
+    }
+    env_logger::builder().format_timestamp_millis().init();
+
+    let config = load_config(&opts.config);
+    let max_failures = opts.max_failures.unwrap_or(0.0);
+    check_max_failures(&config, max_failures);
+    let area = match config.area() {
+        Ok(area) => area,
+        Err(err) => {
+            log::error!("Invalid search area: {err}");
+            std::process::exit(1);
+        }
+    };
+    let (y_min, y_max) = config.y_range();
+    let formation: Vec<RotationInfo> = config
+        .formation
+        .iter()
+        .map(|entry| entry.rotation)
+        .collect();
+
+    // The entries of a block only count together
+    let blocks: Vec<Vec<&RotationInfo>> = scoring::blocks(&formation)
+        .iter()
+        .map(|block| block.iter().map(|&index| &formation[index]).collect())
+        .collect();
+    let bits: f64 = match blocks
+        .iter()
+        .map(|block| analyze::information_bits(block))
+        .sum()
+    {
+        Ok(bits) => bits,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "The formation has {} entries with {bits:.1} bits of information (a random position matches 1 in {:.0})",
+        formation.len(),
+        bits.exp2()
+    );
+
+    let columns = area.estimate_columns(1000);
+    let ys = match config.surface_window {
+        Some(window) => (2 * window + 1).min(y_max - y_min + 1),
+        None => y_max - y_min + 1,
+    };
+    let facings = match config.facing {
+        Some(facing) => vec![facing],
+        None => Facing::ALL.to_vec(),
+    };
+    let positions = columns * ys as f64 * facings.len() as f64;
+    println!(
+        "The search area has {columns:.0} columns x {ys} Y levels x {} facings = {positions:.3e} positions (before biome and structure filters)",
+        facings.len()
+    );
+    println!(
+        "A unique match needs about {:.1} bits, without allowing any failures",
+        positions.log2()
+    );
+
+    println!("Expected false positives with failures up to a score of {max_failures}:");
+    let bounds = area.bounds();
+    let expected = |name: &str, frequencies: analyze::RotationFrequencies| {
+        let chance = analyze::false_positive_chance(&formation, &frequencies, max_failures);
+        let exact: f64 = blocks
+            .iter()
+            .map(|block| frequencies.match_chance(block))
+            .product();
+        let selected = if name == config.textures {
+            " (selected)"
+        } else {
+            ""
+        };
+        println!(
+            "  {name}: {:.3e}, an exact match is 1 in {:.0}{selected}",
+            chance * positions,
+            1.0 / exact
+        );
+    };
+    expected(
+        SodiumTextures::NAME,
+        analyze::RotationFrequencies::measure(&SodiumTextures {}, &bounds, (y_min, y_max)),
+    );
+    expected(
+        Sodium19Textures::NAME,
+        analyze::RotationFrequencies::measure(&Sodium19Textures {}, &bounds, (y_min, y_max)),
+    );
+    expected(
+        VanillaTextures::NAME,
+        analyze::RotationFrequencies::measure(&VanillaTextures {}, &bounds, (y_min, y_max)),
+    );
+
+    for turn in analyze::symmetries(&formation, &facings) {
+        log::warn!(
+            "The formation matches itself turned {}°, so hits can't tell these apart. Add entries that break the symmetry.",
+            turn.quarter_turns() * 90
+        );
+    }
+}
+
+fn verify(mut opts: VerifyOpts) {
+    if opts.is_side {
+        opts.face = Face::North;
+    }
+    let modulo = opts.face.modulo();
     let (x, y, z) = (opts.x, opts.y, opts.z);
 
     let sod = SodiumTextures {}.get_texture(x, y, z, modulo);
     let sod19 = Sodium19Textures {}.get_texture(x, y, z, modulo);
     let van = VanillaTextures {}.get_texture(x, y, z, modulo);
 
-    println!("Rotation values at {x}, {y}, {z}{} are {sod} (Sodium), {sod19} (Sodium19) and {van} (Vanilla)", if opts.is_side { " (is side)" } else { "" });
+    println!("Rotation values at {x}, {y}, {z} ({} face) are {sod} (Sodium), {sod19} (Sodium19) and {van} (Vanilla)", opts.face);
 }
 
 fn scan(opts: ScanOpts) {
@@ -100,25 +934,32 @@ fn scan(opts: ScanOpts) {
     env_logger::builder().format_timestamp_millis().init();
 
     let config_path = &opts.config;
-    if !config_path.exists() || config_path.is_dir() {
-        log::error!(
-            "Failed to load config ({config_path:?}). The file doesn't exist or is not a directory!"
-        );
-        std::process::exit(1);
-    }
-
-    let config_content = std::fs::read_to_string(config_path).expect("Reading toml config failed");
-    let mut config: Config = toml::from_str(&config_content).expect("Parsing toml config failed");
+    let mut config = load_config(config_path);
 
     // Sanity checks
-    assert!(config.x_min < config.x_max);
-    assert!(config.y_min < config.y_max);
-    assert!(config.z_min < config.z_max);
-
-    // Fix rotation values
-    for rotation_info in &mut config.formation {
-        rotation_info.fix_rotation();
+    let (y_min, y_max) = config.y_range();
+    if y_min > y_max {
+        log::error!("y_min ({y_min}) is above y_max ({y_max})!");
+        std::process::exit(1);
+    }
+    let area = match config.area() {
+        Ok(area) => Arc::new(area),
+        Err(err) => {
+            log::error!("Invalid search area: {err}");
+            std::process::exit(1);
+        }
+    };
+    let bounds = area.bounds();
+    let (lowest_y, highest_y) = world::build_limits(config.mc_version, config.dimension);
+    if y_min < lowest_y || y_max > highest_y {
+        log::warn!(
+            "The Y range {y_min} to {y_max} is outside of the build limits ({lowest_y} to {highest_y}) of the {} in {}",
+            config.dimension,
+            config.mc_version
+        );
     }
+    let entry_biomes = resolve_biomes(&mut config);
+    check_column_filters(&config);
 
     // Select texture provider
     match config.textures.as_str() {
@@ -133,18 +974,51 @@ fn scan(opts: ScanOpts) {
 
     //log::debug!("Config: {config:#?}");
     log::debug!("Using config {:?}:", config_path);
-    log::debug!("  X: {} (min) to {} (max)", config.x_min, config.x_max);
-    log::debug!("  Y: {} (min) to {} (max)", config.y_min, config.y_max);
-    log::debug!("  Z: {} (min) to {} (max)", config.z_min, config.z_max);
+    log::debug!("  X: {} (min) to {} (max)", bounds.x_min, bounds.x_max);
+    log::debug!("  Y: {} (min) to {} (max)", y_min, y_max);
+    log::debug!("  Z: {} (min) to {} (max)", bounds.z_min, bounds.z_max);
+    if !area.is_rect() {
+        log::debug!(
+            "  Only scanning inside {} regions, except {} excluded ones",
+            area.regions.len(),
+            area.exclude.len()
+        );
+    }
     log::debug!("  {} threads", config.threads);
-    if config.filter_for_biome_ids.len() > 0 {
-        log::debug!("  Filtering for biomes: {:?}", config.filter_for_biome_ids);
+    if let Some(seed) = config.seed {
+        log::debug!(
+            "  Seed {seed} ({} in {})",
+            config.dimension,
+            config.mc_version
+        );
+    }
+    if !config.filter_for_biome_ids.is_empty() {
+        let mut names: Vec<_> = config
+            .filter_for_biome_ids
+            .iter()
+            .map(|&id| match biomes::name_of(id, config.mc_version) {
+                Some(name) => name.to_owned(),
+                None => format!("{id:?}"),
+            })
+            .collect();
+        names.sort_unstable();
+        log::debug!("  Filtering for biomes: {}", names.join(", "));
+    }
+    for near in &config.near_structures {
+        log::debug!(
+            "  Only scanning within {} blocks of a {}",
+            near.distance,
+            near.structure
+        );
+    }
+    if let Some(window) = config.surface_window {
+        log::debug!("  Only scanning {window} blocks around the approximate surface");
     }
     log::debug!("  The formation has {} rotations", config.formation.len());
-    let placement = placement::Placement::new(&config.formation);
-
-    let x_total: i32 = config.x_max - config.x_min;
-    let per_x: i32 = x_total / config.threads;
+    if let Some(facing) = config.facing {
+        log::debug!("  Only checking the formation facing {facing}");
+    }
+    let placements = placements(&config, entry_biomes.clone());
 
     // Thread pinning
     let mut core_ids = if config.pin_threads_to_cores {
@@ -162,26 +1036,178 @@ fn scan(opts: ScanOpts) {
 
     // Check max_failures value
     if let Some(max_failures) = opts.max_failures {
-        if max_failures == 0 {
+        if max_failures == 0.0 {
             log::warn!(
                 "Just remove this argument. You'll otherwise just waste resources for no gain. ;)"
             );
         }
-        if max_failures >= config.formation.len() {
-            log::error!("You shouldn't allow more failures then actual blocks in your formation!");
+        check_max_failures(&config, max_failures);
+    }
+
+    if opts.top == Some(0) {
+        log::error!("--top needs to keep at least one match!");
+        std::process::exit(1);
+    }
+
+    // Load checkpoint when resuming
+    let config_hash = checkpoint::config_hash(&ScanIdentity::new(
+        &config,
+        &entry_biomes,
+        opts.max_failures,
+        opts.top,
+    ));
+    let resumed = opts.resume.as_ref().map(|path| {
+        let checkpoint = match Checkpoint::load(path) {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                log::error!("Failed to load checkpoint {path:?}: {err}");
+                std::process::exit(1);
+            }
+        };
+        if checkpoint.config_hash != config_hash {
+            log::error!(
+                "The checkpoint {path:?} was created with a different formation, area, filters, --max-failures or --top!"
+            );
             std::process::exit(1);
         }
+        log::info!(
+            "Resuming with {} tiles already scanned and {} hits found",
+            checkpoint.completed.len(),
+            checkpoint.hits.len()
+        );
+        checkpoint
+    });
+    let known_hits: HashSet<_> = resumed
+        .as_ref()
+        .map(|checkpoint| checkpoint.hits.iter().cloned().collect())
+        .unwrap_or_default();
+    let top_candidates = resumed
+        .as_ref()
+        .map(|checkpoint| checkpoint.top_candidates.clone())
+        .unwrap_or_default();
+    let save_checkpoints_periodically = !opts.no_checkpoint;
+    let checkpoint_path = opts
+        .checkpoint
+        .clone()
+        .or_else(|| opts.resume.clone())
+        .unwrap_or_else(|| config_path.with_extension("checkpoint.json"));
+    let checkpoint = Arc::new(SharedCheckpoint::new(
+        checkpoint_path,
+        resumed.unwrap_or_else(|| Checkpoint::new(config_hash)),
+    ));
+
+    // Create result sinks
+    let mut sinks: Vec<Box<dyn ResultSink>> =
+        vec![Box::new(CheckpointSink(Arc::clone(&checkpoint)))];
+    if !opts.no_stdout {
+        sinks.push(Box::new(JsonLinesSink::new(std::io::stdout())));
+    }
+    for path in &opts.output {
+        match result_sink::create_file_sink(path) {
+            Ok(sink) => sinks.push(sink),
+            Err(err) => {
+                log::error!("Failed to create output file {path:?}: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    // The best matches are reported at the end, except the ones reported by an earlier run
+    let top = opts.top.map(|k| {
+        let top = TopHits::new(
+            k,
+            opts.max_failures
+                .unwrap_or_else(|| config.default_max_failures()),
+        );
+        for hit in known_hits.iter().chain(&top_candidates) {
+            top.offer(hit.clone());
+        }
+        Arc::new(top)
+    });
+    let results = Arc::new(Results::new(sinks, known_hits).with_max_new_hits(opts.max_results));
+
+    // Split the area into tiles
+    let parts: Vec<Rect> = area.regions.iter().map(Region::bounds).collect();
+    let scheduler = Arc::new(Scheduler::new(
+        &parts,
+        config.tile_size.unwrap_or(DEFAULT_TILE_SIZE),
+        config.threads as usize,
+        config.order.as_ref(),
+        |tile| checkpoint.is_completed(tile.index as i64) || !area.intersects_rect(&tile.rect()),
+    ));
+
+    // Tiles that were stopped in between only have the columns from where they stopped left.
+    // Columns outside of the area don't count.
+    let pending_columns = scheduler
+        .pending_tiles()
+        .iter()
+        .map(|tile| {
+            let rect = Rect {
+                x_min: checkpoint.resume_x(tile.index).unwrap_or(tile.x_min),
+                ..tile.rect()
+            };
+            area.estimate_columns_in(&rect, TILE_SAMPLES).round() as u64
+        })
+        .sum();
+    log::debug!(
+        "Split the area into {} tiles along {:?} ({} columns left to scan)",
+        scheduler.tile_count(),
+        scheduler.split_axis,
+        pending_columns
+    );
+
+    let progress = Arc::new(Progress::new(
+        pending_columns,
+        match config.surface_window {
+            Some(window) => (2 * window + 1).min(y_max - y_min + 1) as u64,
+            None => (y_max - y_min + 1) as u64,
+        },
+    ));
+
+    // Stop at the next column on Ctrl-C or SIGTERM. A second one exits immediately.
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = Arc::clone(&stop);
+        ctrlc::set_handler(move || {
+            if stop.swap(true, Ordering::Relaxed) {
+                log::warn!("Exiting immediately!");
+                std::process::exit(130);
+            }
+            log::warn!(
+                "Stopping after the current column. Press Ctrl-C again to exit immediately."
+            );
+        })
+        .expect("Setting signal handler failed");
     }
 
-    let max_failures = opts.max_failures;
+    let biome_map = match config.world() {
+        Some(world) if config.needs_biomes() => Some(Arc::new(BiomeMap::new(
+            world,
+            config.biome_map_mib.unwrap_or(DEFAULT_BIOME_MAP_MIB),
+            config.biome_map_dir.clone(),
+        ))),
+        _ => None,
+    };
+
+    // Workers take the tightening cutoff from the top hits
+    let max_failures = match &top {
+        Some(_) => Some(
+            opts.max_failures
+                .unwrap_or_else(|| config.default_max_failures()),
+        ),
+        None => opts.max_failures,
+    };
     // Create threads
     let mut thread_handles = vec![];
-    for (i, start) in (config.x_min..config.x_max)
-        .step_by(per_x as usize + 1)
-        .enumerate()
-    {
-        let placement = placement.clone();
+    for i in 0..config.threads as usize {
+        let placements = placements.clone();
+        let scheduler = Arc::clone(&scheduler);
+        let results = Arc::clone(&results);
+        let progress = Arc::clone(&progress);
+        let checkpoint = Arc::clone(&checkpoint);
+        let stop = Arc::clone(&stop);
+        let top = top.clone();
         let config = config.clone();
+        let biome_map = biome_map.clone();
 
         let core_id = core_ids.as_mut().map(|ids| ids[i % ids.len()]);
         thread_handles.push(
@@ -197,128 +1223,334 @@ fn scan(opts: ScanOpts) {
                         );
                     }
 
-                    let biome_filter = if config.filter_for_biome_ids.len() > 0 {
-                        Some((
-                            cubiomes::finders::CubiomesFinder::new(
-                                LO_SEED,
-                                libcubiomes_sys::MCVersion_MC_1_19,
-                                libcubiomes_sys::Dimension_DIM_OVERWORLD,
-                            ),
-                            config.filter_for_biome_ids.clone(),
-                        ))
-                    } else {
-                        None
-                    };
+                    let biome_map = biome_map.map(BiomeMapReader::new);
 
+                    let finder = FinderParts {
+                        worker: i,
+                        scheduler,
+                        config: &config,
+                        biome_map,
+                        placements,
+                        results,
+                        progress,
+                        checkpoint,
+                        stop,
+                        top,
+                    };
                     match config.textures.as_str() {
-                        "Sodium" => {
-                            if let Some(max_failures) = max_failures {
-                                texture_finder::TextureFinder {
-                                    start_x: start,
-                                    end_x: start + per_x,
-                                    y_min: config.y_min,
-                                    y_max: config.y_max,
-                                    z_min: config.z_min,
-                                    z_max: config.z_max,
-                                    textures: SodiumTextures {},
-                                    biome_filter,
-                                    biome_cache: None,
-                                    biome_cache_probe_count: 0,
-                                    placement,
-                                }
-                                .run_with_tolerance(max_failures)
-                            } else {
-                                texture_finder::TextureFinder {
-                                    start_x: start,
-                                    end_x: start + per_x,
-                                    y_min: config.y_min,
-                                    y_max: config.y_max,
-                                    z_min: config.z_min,
-                                    z_max: config.z_max,
-                                    textures: SodiumTextures {},
-                                    biome_filter,
-                                    biome_cache: None,
-                                    biome_cache_probe_count: 0,
-                                    placement,
-                                }
-                                .run()
-                            }
-                        }
-                        "Sodium19" => {
-                            if let Some(max_failures) = max_failures {
-                                texture_finder::TextureFinder {
-                                    start_x: start,
-                                    end_x: start + per_x,
-                                    y_min: config.y_min,
-                                    y_max: config.y_max,
-                                    z_min: config.z_min,
-                                    z_max: config.z_max,
-                                    textures: Sodium19Textures {},
-                                    biome_filter,
-                                    biome_cache: None,
-                                    biome_cache_probe_count: 0,
-                                    placement,
-                                }
-                                .run_with_tolerance(max_failures)
-                            } else {
-                                texture_finder::TextureFinder {
-                                    start_x: start,
-                                    end_x: start + per_x,
-                                    y_min: config.y_min,
-                                    y_max: config.y_max,
-                                    z_min: config.z_min,
-                                    z_max: config.z_max,
-                                    textures: Sodium19Textures {},
-                                    biome_filter,
-                                    biome_cache: None,
-                                    biome_cache_probe_count: 0,
-                                    placement,
-                                }
-                                .run()
-                            }
-                        }
-                        "Vanilla" => {
-                            if let Some(max_failures) = max_failures {
-                                texture_finder::TextureFinder {
-                                    start_x: start,
-                                    end_x: start + per_x,
-                                    y_min: config.y_min,
-                                    y_max: config.y_max,
-                                    z_min: config.z_min,
-                                    z_max: config.z_max,
-                                    textures: VanillaTextures {},
-                                    biome_filter,
-                                    biome_cache: None,
-                                    biome_cache_probe_count: 0,
-                                    placement,
-                                }
-                                .run_with_tolerance(max_failures)
-                            } else {
-                                texture_finder::TextureFinder {
-                                    start_x: start,
-                                    end_x: start + per_x,
-                                    y_min: config.y_min,
-                                    y_max: config.y_max,
-                                    z_min: config.z_min,
-                                    z_max: config.z_max,
-                                    textures: VanillaTextures {},
-                                    biome_filter,
-                                    biome_cache: None,
-                                    biome_cache_probe_count: 0,
-                                    placement,
-                                }
-                                .run()
-                            }
-                        }
+                        "Sodium" => finder.run(SodiumTextures {}, max_failures),
+                        "Sodium19" => finder.run(Sodium19Textures {}, max_failures),
+                        "Vanilla" => finder.run(VanillaTextures {}, max_failures),
                         _ => panic!("Unknown name!"),
-                    };
+                    }
                 })
                 .unwrap(),
         );
     }
 
-    // Wait for all threads to finish
-    thread_handles
-        .into_iter()
-        .for_each(|handle| handle.join().unwrap());
+    // Wait for all threads to finish. Report progress and save checkpoints in between.
+    let checkpoint_interval = Duration::from_secs(opts.checkpoint_interval);
+    let progress_interval = Duration::from_secs(opts.progress_interval);
+    let mut last_checkpoint = Instant::now();
+    let mut last_progress = Instant::now();
+    let save_checkpoint = |checkpoint: &SharedCheckpoint| {
+        if let Some(top) = &top {
+            checkpoint.set_top_candidates(top.hits());
+        }
+        save_checkpoint(checkpoint);
+    };
+    let time_budget: Option<Duration> = opts.time_budget.map(Into::into);
+    while thread_handles.iter().any(|handle| !handle.is_finished()) {
+        std::thread::sleep(Duration::from_millis(100));
+        if time_budget.is_some_and(|budget| progress.elapsed() >= budget)
+            && !stop.swap(true, Ordering::Relaxed)
+        {
+            log::info!("The time budget is used up. Stopping after the current column.");
+        }
+        if last_progress.elapsed() >= progress_interval {
+            progress.log_status();
+            last_progress = Instant::now();
+        }
+        if save_checkpoints_periodically && last_checkpoint.elapsed() >= checkpoint_interval {
+            save_checkpoint(&checkpoint);
+            last_checkpoint = Instant::now();
+        }
+    }
+    let mut failed = false;
+    for handle in thread_handles {
+        if let Err(err) = handle.join().unwrap() {
+            log::error!("{err}");
+            failed = true;
+        }
+    }
+    // Only a completed scan knows its best matches. Otherwise they stay in the checkpoint.
+    let stopped = stop.load(Ordering::Relaxed);
+    if let Some(top) = &top {
+        let hits = top.hits();
+        if stopped {
+            log::info!("The {} best matches so far (provisional):", hits.len());
+        } else {
+            log::info!("The {} best matches:", hits.len());
+        }
+        for (rank, hit) in hits.iter().enumerate() {
+            log::info!(
+                "{}. X {} Y {} Z {} facing {:?}: score {}, p-value {:.3e}, {} failed entries {:?}",
+                rank + 1,
+                hit.x,
+                hit.y,
+                hit.z,
+                hit.facing,
+                hit.score,
+                hit.p_value,
+                hit.fails,
+                hit.failed_entries
+            );
+            if !stopped && results.report(hit) {
+                progress.add_hit();
+            }
+        }
+    }
+    results.flush();
+    if save_checkpoints_periodically || stopped {
+        save_checkpoint(&checkpoint);
+    }
+    if results.limit_reached() {
+        log::info!("Stopped after finding {} new results", progress.hits());
+    }
+    progress.log_summary();
+    if !opts.no_biome_check_benchmark {
+        benchmark_biome_check(&config, &placements, max_failures);
+    }
+    if stopped {
+        log::warn!(
+            "The scan was stopped early. Continue it with --resume {:?}",
+            checkpoint.path()
+        );
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Scan a small part of the area with the biome check before and after the rotation
+/// check and log which one was faster, to choose lazy_biome_check. Does nothing without
+/// biomes to filter for or a seed.
+fn benchmark_biome_check(
+    config: &Config,
+    placements: &[(Facing, placement::Placement)],
+    max_failures: Option<f64>,
+) {
+    let world = match config.world() {
+        Some(world) if !config.filter_for_biome_ids.is_empty() => world,
+        _ => return,
+    };
+    let time = |lazy| match config.textures.as_str() {
+        "Sodium" => time_scan(
+            SodiumTextures {},
+            config,
+            world,
+            placements,
+            max_failures,
+            lazy,
+        ),
+        "Sodium19" => time_scan(
+            Sodium19Textures {},
+            config,
+            world,
+            placements,
+            max_failures,
+            lazy,
+        ),
+        "Vanilla" => time_scan(
+            VanillaTextures {},
+            config,
+            world,
+            placements,
+            max_failures,
+            lazy,
+        ),
+        _ => panic!("Unknown name!"),
+    };
+    let eager = time(false);
+    let lazy = time(true);
+    let (faster, factor) = if lazy < eager {
+        ("after", eager.as_secs_f64() / lazy.as_secs_f64().max(1e-9))
+    } else {
+        ("before", lazy.as_secs_f64() / eager.as_secs_f64().max(1e-9))
+    };
+    log::info!(
+        "Biome check benchmark ({BIOME_BENCHMARK_SIZE}x{BIOME_BENCHMARK_SIZE} columns): {eager:?} before and {lazy:?} after the rotations. Checking biomes {faster} the rotations was {factor:.1}x faster (recommended: lazy_biome_check = {}).",
+        faster == "after"
+    );
+}
+
+/// Time scanning a corner inside the area without reporting any hits.
+fn time_scan<T: TextureProvider>(
+    textures: T,
+    config: &Config,
+    world: World,
+    placements: &[(Facing, placement::Placement)],
+    max_failures: Option<f64>,
+    lazy_biome_check: bool,
+) -> Duration {
+    let (y_min, y_max) = config.y_range();
+    let area = config.area().unwrap();
+    let bounds = area.bounds();
+    let (x, z) = area
+        .find_column(TILE_SAMPLES)
+        .unwrap_or((bounds.x_min, bounds.z_min));
+    let biome_map = Arc::new(BiomeMap::new(world, DEFAULT_BIOME_MAP_MIB, None));
+    let mut finder = texture_finder::TextureFinder {
+        start_x: x,
+        end_x: bounds.x_max.min(x + BIOME_BENCHMARK_SIZE - 1),
+        y_min,
+        y_max,
+        z_min: z,
+        z_max: bounds.z_max.min(z + BIOME_BENCHMARK_SIZE - 1),
+        textures,
+        area: config.area_filter(),
+        world: Some(world),
+        hit_finder: None,
+        biome_map: Some(BiomeMapReader::new(biome_map)),
+        biome_filter: config.biome_filter(),
+        lazy_biome_check,
+        structure_filter: config.structure_filter(),
+        surface: config.surface_filter(),
+        placements: Arc::new(placements.to_vec()),
+        results: Arc::new(Results::new(vec![], HashSet::new())),
+        progress: Arc::new(Progress::new(0, 0)),
+        stop: Arc::new(AtomicBool::new(false)),
+        top: None,
+    };
+    let start = Instant::now();
+    let result = match max_failures {
+        Some(max_failures) => finder.run_with_tolerance(max_failures),
+        None => finder.run(),
+    };
+    if let Err(err) = result {
+        log::warn!("Biome check benchmark failed: {err}");
+    }
+    start.elapsed()
+}
+
+/// Check every candidate again in the facing it was found in. Returns how many still match.
+fn recheck_hits<T: TextureProvider>(
+    textures: T,
+    config: &Config,
+    placements: &[(Facing, placement::Placement)],
+    biome_map: Option<Arc<BiomeMap>>,
+    results: &Arc<Results>,
+    candidates: &[result_sink::Hit],
+    max_failures: f64,
+) -> Result<usize, String> {
+    let (y_min, y_max) = config.y_range();
+    let mut finder = texture_finder::TextureFinder {
+        start_x: 0,
+        end_x: 0,
+        y_min,
+        y_max,
+        z_min: 0,
+        z_max: 0,
+        textures,
+        // Checked before rechecking
+        area: Some(config.area().unwrap()),
+        world: config.world(),
+        hit_finder: None,
+        biome_map: biome_map.map(BiomeMapReader::new),
+        biome_filter: config.biome_filter(),
+        lazy_biome_check: true,
+        structure_filter: config.structure_filter(),
+        surface: config.surface_filter(),
+        placements: Arc::new(placements.to_vec()),
+        results: Arc::clone(results),
+        progress: Arc::new(Progress::new(0, 0)),
+        stop: Arc::new(AtomicBool::new(false)),
+        top: None,
+    };
+    let mut matching = 0;
+    for hit in candidates {
+        if finder.recheck((hit.x, hit.y, hit.z), hit.facing, max_failures)? {
+            matching += 1;
+        }
+    }
+    Ok(matching)
+}
+
+fn save_checkpoint(checkpoint: &SharedCheckpoint) {
+    if let Err(err) = checkpoint.save() {
+        log::error!("Failed to save checkpoint: {err}");
+    }
+}
+
+/// Everything needed to create a [`texture_finder::TextureFinder`] besides the textures.
+struct FinderParts<'a> {
+    worker: usize,
+    scheduler: Arc<Scheduler>,
+    config: &'a Config,
+    biome_map: Option<BiomeMapReader>,
+    placements: Vec<(Facing, placement::Placement)>,
+    results: Arc<Results>,
+    progress: Arc<Progress>,
+    checkpoint: Arc<SharedCheckpoint>,
+    stop: Arc<AtomicBool>,
+    top: Option<Arc<TopHits>>,
+}
+
+impl FinderParts<'_> {
+    /// Scan tiles until the scheduler runs out of them. On an error all workers are stopped.
+    fn run<T: TextureProvider>(self, textures: T, max_failures: Option<f64>) -> Result<(), String> {
+        let (y_min, y_max) = self.config.y_range();
+        let mut finder = texture_finder::TextureFinder {
+            start_x: 0,
+            end_x: 0,
+            y_min,
+            y_max,
+            z_min: 0,
+            z_max: 0,
+            textures,
+            area: self.config.area_filter(),
+            world: self.config.world(),
+            hit_finder: None,
+            biome_map: self.biome_map,
+            biome_filter: self.config.biome_filter(),
+            lazy_biome_check: self.config.lazy_biome_check,
+            structure_filter: self.config.structure_filter(),
+            surface: self.config.surface_filter(),
+            placements: Arc::new(self.placements),
+            results: self.results,
+            progress: self.progress,
+            stop: Arc::clone(&self.stop),
+            top: self.top,
+        };
+
+        let first = Instant::now();
+        let mut tiles = 0;
+        while let Some(tile) = self.scheduler.next(self.worker) {
+            // Continue where a previous scan of this tile was stopped
+            finder.start_x = self.checkpoint.resume_x(tile.index).unwrap_or(tile.x_min);
+            finder.end_x = tile.x_max;
+            finder.z_min = tile.z_min;
+            finder.z_max = tile.z_max;
+            let stopped_at = if let Some(max_failures) = max_failures {
+                finder.run_with_tolerance(max_failures)
+            } else {
+                finder.run()
+            };
+            // The tile gets scanned again on resume, from where it was stopped before
+            let stopped_at =
+                stopped_at.inspect_err(|_| self.stop.store(true, Ordering::Relaxed))?;
+            if let Some(x) = stopped_at {
+                self.checkpoint.stopped_at(tile.index, x);
+                break;
+            }
+            self.checkpoint.complete(tile.index as i64);
+            tiles += 1;
+        }
+        log::debug!(
+            "[{}] Finished {tiles} tiles after {:?}",
+            std::thread::current().name().unwrap_or("Unnamed Thread"),
+            first.elapsed()
+        );
+        Ok(())
+    }
 }
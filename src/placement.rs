@@ -1,25 +1,152 @@
-use crate::rotation_info::RotationInfo;
+use crate::{
+    facing::Facing,
+    rotation_info::{Face, RotationInfo},
+    scoring::Scoring,
+};
+use cubiomes::finders::BiomeID;
+use std::collections::HashSet;
 
 // TODO: Remove need for clone
 #[derive(Debug, Clone)]
 pub struct Placement {
-    pub tops_and_bottoms: Vec<RotationInfo>,
+    /// All entries in the order of the formation
+    pub entries: Vec<RotationInfo>,
+    pub tops: Vec<RotationInfo>,
+    pub bottoms: Vec<RotationInfo>,
     pub sides: Vec<RotationInfo>,
+    /// Biomes every entry has to be in (same order as `entries`)
+    pub entry_biomes: Vec<Option<HashSet<BiomeID>>>,
+    pub scoring: Scoring,
 }
 
 impl Placement {
     pub fn new(formation: &[RotationInfo]) -> Self {
-        let (mut tops_and_bottoms, mut sides) = (vec![], vec![]);
-        for info in formation {
-            if info.is_side {
-                sides.push(*info);
-            } else {
-                tops_and_bottoms.push(*info);
+        let (mut tops, mut bottoms, mut sides) = (vec![], vec![], vec![]);
+        // Entries with unknown rotations are only kept in `entries`, as they always match
+        for info in formation.iter().filter(|info| !info.is_unknown()) {
+            match info.face {
+                Face::Up => tops.push(*info),
+                Face::Down => bottoms.push(*info),
+                _ => sides.push(*info),
             }
         }
         Self {
-            tops_and_bottoms,
+            entries: formation.to_vec(),
+            tops,
+            bottoms,
             sides,
+            entry_biomes: vec![None; formation.len()],
+            scoring: Scoring::new(formation, &vec![1.0; formation.len()]),
         }
     }
+
+    /// Set the biomes each entry has to be in.
+    pub fn with_entry_biomes(mut self, entry_biomes: Vec<Option<HashSet<BiomeID>>>) -> Self {
+        assert_eq!(entry_biomes.len(), self.entries.len());
+        self.entry_biomes = entry_biomes;
+        self
+    }
+
+    /// Set how confident the rotation of each entry is, for scoring hits.
+    pub fn with_entry_confidences(mut self, confidences: &[f64]) -> Self {
+        self.scoring = Scoring::new(&self.entries, confidences);
+        self
+    }
+
+    /// Get this placement as it would appear in the world when the player was looking
+    /// in the given direction. Positions are turned and the rotations adjusted, so the
+    /// result can be compared against the textures directly.
+    ///
+    /// Top faces are a rotation of the whole block model, so they turn along with the
+    /// formation. Bottom faces are the same rotation seen from below, which mirrors it, so
+    /// they turn the other way. Side faces only show whether a texture is mirrored, which
+    /// doesn't change when turning.
+    pub fn turned(&self, facing: Facing) -> Self {
+        let quarter_turns = facing.quarter_turns();
+        let turn_rotation = |info: &RotationInfo| match info.face {
+            Face::Up => info.rotation.turned(quarter_turns),
+            Face::Down => info.rotation.turned(-quarter_turns),
+            _ => info.rotation,
+        };
+        let turn = |info: &RotationInfo| {
+            let (x, z) = facing.turn(info.x, info.z);
+            RotationInfo {
+                x,
+                z,
+                rotation: turn_rotation(info),
+                face: info.face.turned(facing),
+                ..*info
+            }
+        };
+        Self {
+            entries: self.entries.iter().map(turn).collect(),
+            tops: self.tops.iter().map(turn).collect(),
+            bottoms: self.bottoms.iter().map(turn).collect(),
+            sides: self.sides.iter().map(turn).collect(),
+            entry_biomes: self.entry_biomes.clone(),
+            scoring: self.scoring.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation_info::Rotations;
+
+    /// The direction a rotation points to on a top face viewed from above: 0 is north
+    /// (-Z), then clockwise.
+    const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+    fn direction_index(direction: (i32, i32)) -> i32 {
+        DIRECTIONS.iter().position(|&d| d == direction).unwrap() as i32
+    }
+
+    #[test]
+    fn top_rotations_turn_with_the_formation() {
+        for rotation in 0..4 {
+            let placement = Placement::new(&[RotationInfo::new(1, 0, -2, rotation, Face::Up)]);
+            for facing in Facing::ALL {
+                let turned = placement.turned(facing);
+                let top = turned.tops[0];
+                assert_eq!((top.x, top.z), facing.turn(1, -2));
+                // The texture points wherever the formation turns its direction to
+                let (x, z) = DIRECTIONS[rotation as usize];
+                let expected = direction_index(facing.turn(x, z));
+                assert_eq!(top.rotation, Rotations::single(expected), "{facing}");
+                assert_eq!(turned.entries[0], top);
+            }
+        }
+    }
+
+    #[test]
+    fn bottom_rotations_turn_mirrored() {
+        // Seen from below, east and west swap places
+        let mirror = |(x, z): (i32, i32)| (-x, z);
+        for rotation in 0..4 {
+            let placement = Placement::new(&[RotationInfo::new(0, 0, 0, rotation, Face::Down)]);
+            for facing in Facing::ALL {
+                let bottom = placement.turned(facing).bottoms[0];
+                let direction = mirror(DIRECTIONS[rotation as usize]);
+                let expected = direction_index(mirror(facing.turn(direction.0, direction.1)));
+                assert_eq!(bottom.rotation, Rotations::single(expected), "{facing}");
+                // A quarter turn clockwise turns the bottom counter-clockwise
+                let counter_clockwise = (rotation - facing.quarter_turns()).rem_euclid(4);
+                assert_eq!(bottom.rotation, Rotations::single(counter_clockwise));
+            }
+        }
+    }
+
+    #[test]
+    fn sides_keep_their_rotation_and_turn_their_face() {
+        let placement = Placement::new(&[
+            RotationInfo::new(0, 0, 0, 1, Face::North),
+            RotationInfo::new(0, 0, 0, 0, Face::West),
+        ]);
+        let turned = placement.turned(Facing::East);
+        assert_eq!(turned.sides[0].face, Face::East);
+        assert_eq!(turned.sides[0].rotation, Rotations::single(1));
+        assert_eq!(turned.sides[1].face, Face::North);
+        assert_eq!(turned.sides[1].rotation, Rotations::single(0));
+    }
 }
@@ -0,0 +1,125 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Collects the progress of all workers of a scan.
+pub struct Progress {
+    start: Instant,
+    total_columns: u64,
+    positions_per_column: u64,
+    columns_done: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(total_columns: u64, positions_per_column: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            total_columns,
+            positions_per_column,
+            columns_done: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add_columns(&self, columns: u64) {
+        self.columns_done.fetch_add(columns, Ordering::Relaxed);
+    }
+
+    pub fn add_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn columns_done(&self) -> u64 {
+        self.columns_done.load(Ordering::Relaxed)
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// The total of a non-rectangular area is estimated, so more columns can get done.
+    fn total_columns(&self, done: u64) -> u64 {
+        self.total_columns.max(done)
+    }
+
+    fn positions_per_second(&self, done: u64, elapsed: Duration) -> f64 {
+        let positions = done * self.positions_per_column;
+        positions as f64 / elapsed.as_secs_f64().max(0.001)
+    }
+
+    /// Time left if the remaining columns go as fast as the ones done so far.
+    fn eta(&self, done: u64, elapsed: Duration) -> Option<Duration> {
+        if done == 0 {
+            return None;
+        }
+        let remaining = self.total_columns(done) - done;
+        Some(elapsed.mul_f64(remaining as f64 / done as f64))
+    }
+
+    pub fn log_status(&self) {
+        let (done, elapsed) = (self.columns_done(), self.elapsed());
+        let total = self.total_columns(done);
+        let percent = done as f64 * 100.0 / total.max(1) as f64;
+        let eta = self
+            .eta(done, elapsed)
+            .map_or_else(|| "unknown".to_owned(), format_duration);
+        log::info!(
+            "Progress: {percent:.2}% ({done} of {total} columns), {:.0} positions/s, {} hits, {eta} remaining",
+            self.positions_per_second(done, elapsed),
+            self.hits(),
+        );
+    }
+
+    pub fn log_summary(&self) {
+        let (done, elapsed) = (self.columns_done(), self.elapsed());
+        let total = self.total_columns(done);
+        let percent = done as f64 * 100.0 / total.max(1) as f64;
+        log::info!(
+            "Scanned {done} of {total} columns ({percent:.2}%, {} positions) in {}, {:.0} positions/s, {} hits",
+            done * self.positions_per_column,
+            format_duration(elapsed),
+            self.positions_per_second(done, elapsed),
+            self.hits(),
+        );
+    }
+}
+
+/// Format a duration with a precision of seconds (e.g. "1day 2h 3m 4s").
+pub fn format_duration(duration: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throughput_and_eta() {
+        let progress = Progress::new(1000, 384);
+        let minute = Duration::from_secs(60);
+        assert_eq!(progress.positions_per_second(250, minute), 1600.0);
+        // Nothing done yet, so there is no pace to go by
+        assert_eq!(progress.eta(0, minute), None);
+        assert_eq!(progress.eta(250, minute), Some(minute * 3));
+        assert_eq!(progress.eta(1000, minute), Some(Duration::ZERO));
+        // More columns than estimated are done, so there is nothing left
+        assert_eq!(progress.eta(1200, minute), Some(Duration::ZERO));
+        // No division by zero right at the start
+        assert_eq!(progress.positions_per_second(1, Duration::ZERO), 384_000.0);
+    }
+
+    #[test]
+    fn formats_whole_seconds() {
+        assert_eq!(
+            format_duration(Duration::from_millis(93_784_500)),
+            "1day 2h 3m 4s"
+        );
+        assert_eq!(format_duration(Duration::from_millis(999)), "0s");
+    }
+}
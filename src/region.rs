@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+
+/// Columns sampled along each axis when estimating how much of a tile is inside an area
+pub const TILE_SAMPLES: i64 = 64;
+
+/// A part of the world in XZ. Bounds are inclusive.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "shape", rename_all = "lowercase")]
+pub enum Region {
+    Box {
+        x_min: i32,
+        x_max: i32,
+        z_min: i32,
+        z_max: i32,
+    },
+    Circle {
+        x: i32,
+        z: i32,
+        radius: i32,
+    },
+    /// Everything between two circles around the same center
+    Ring {
+        x: i32,
+        z: i32,
+        inner_radius: i32,
+        outer_radius: i32,
+    },
+    /// Corners as `[x, z]`
+    Polygon {
+        points: Vec<(i32, i32)>,
+    },
+}
+
+/// A rectangle of columns (inclusive bounds).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Rect {
+    pub x_min: i32,
+    pub x_max: i32,
+    pub z_min: i32,
+    pub z_max: i32,
+}
+
+impl Rect {
+    pub fn columns(&self) -> u64 {
+        (self.x_max - self.x_min + 1) as u64 * (self.z_max - self.z_min + 1) as u64
+    }
+
+    /// Squared distances from a point to the nearest and farthest column of the rectangle.
+    fn distances_squared(&self, x: i32, z: i32) -> (i64, i64) {
+        let axis = |min: i32, max: i32, center: i32| {
+            let (min, max, center) = (min as i64, max as i64, center as i64);
+            let nearest = (min - center).max(0).max(center - max);
+            let farthest = (center - min).abs().max((max - center).abs());
+            (nearest, farthest)
+        };
+        let (near_x, far_x) = axis(self.x_min, self.x_max, x);
+        let (near_z, far_z) = axis(self.z_min, self.z_max, z);
+        (
+            near_x * near_x + near_z * near_z,
+            far_x * far_x + far_z * far_z,
+        )
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x_min <= other.x_max
+            && other.x_min <= self.x_max
+            && self.z_min <= other.z_max
+            && other.z_min <= self.z_max
+    }
+}
+
+impl Region {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Region::Box {
+                x_min,
+                x_max,
+                z_min,
+                z_max,
+            } if x_min > x_max || z_min > z_max => Err(format!(
+                "Box from {x_min}, {z_min} to {x_max}, {z_max} is empty"
+            )),
+            Region::Circle { radius, .. } if *radius < 0 => {
+                Err("Circle has a negative radius".to_owned())
+            }
+            Region::Ring {
+                inner_radius,
+                outer_radius,
+                ..
+            } if *inner_radius < 0 || inner_radius > outer_radius => Err(format!(
+                "Ring with radii {inner_radius} to {outer_radius} is empty"
+            )),
+            Region::Polygon { points } if points.len() < 3 => {
+                Err("Polygon needs at least 3 points".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        match self {
+            &Region::Box {
+                x_min,
+                x_max,
+                z_min,
+                z_max,
+            } => Rect {
+                x_min,
+                x_max,
+                z_min,
+                z_max,
+            },
+            &Region::Circle { x, z, radius }
+            | &Region::Ring {
+                x,
+                z,
+                outer_radius: radius,
+                ..
+            } => Rect {
+                x_min: x - radius,
+                x_max: x + radius,
+                z_min: z - radius,
+                z_max: z + radius,
+            },
+            Region::Polygon { points } => Rect {
+                x_min: points.iter().map(|p| p.0).min().unwrap(),
+                x_max: points.iter().map(|p| p.0).max().unwrap(),
+                z_min: points.iter().map(|p| p.1).min().unwrap(),
+                z_max: points.iter().map(|p| p.1).max().unwrap(),
+            },
+        }
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        let distance_squared = |cx: i32, cz: i32| {
+            let (dx, dz) = ((x - cx) as i64, (z - cz) as i64);
+            dx * dx + dz * dz
+        };
+        match self {
+            &Region::Box {
+                x_min,
+                x_max,
+                z_min,
+                z_max,
+            } => (x_min..=x_max).contains(&x) && (z_min..=z_max).contains(&z),
+            &Region::Circle {
+                x: cx,
+                z: cz,
+                radius,
+            } => distance_squared(cx, cz) <= radius as i64 * radius as i64,
+            &Region::Ring {
+                x: cx,
+                z: cz,
+                inner_radius,
+                outer_radius,
+            } => {
+                let distance_squared = distance_squared(cx, cz);
+                distance_squared >= inner_radius as i64 * inner_radius as i64
+                    && distance_squared <= outer_radius as i64 * outer_radius as i64
+            }
+            Region::Polygon { points } => polygon_contains(points, x, z),
+        }
+    }
+
+    /// Whether every column of the rectangle is inside. Can be false for polygons
+    /// even if they do contain it.
+    pub fn contains_rect(&self, rect: &Rect) -> bool {
+        match self {
+            Region::Box { .. } => {
+                let bounds = self.bounds();
+                bounds.x_min <= rect.x_min
+                    && rect.x_max <= bounds.x_max
+                    && bounds.z_min <= rect.z_min
+                    && rect.z_max <= bounds.z_max
+            }
+            &Region::Circle { x, z, radius } => {
+                rect.distances_squared(x, z).1 <= radius as i64 * radius as i64
+            }
+            &Region::Ring {
+                x,
+                z,
+                inner_radius,
+                outer_radius,
+            } => {
+                let (nearest, farthest) = rect.distances_squared(x, z);
+                nearest >= inner_radius as i64 * inner_radius as i64
+                    && farthest <= outer_radius as i64 * outer_radius as i64
+            }
+            Region::Polygon { .. } => false,
+        }
+    }
+
+    /// Whether any column of the rectangle might be inside.
+    pub fn intersects_rect(&self, rect: &Rect) -> bool {
+        if !self.bounds().intersects(rect) {
+            return false;
+        }
+        match self {
+            &Region::Circle { x, z, radius } => {
+                rect.distances_squared(x, z).0 <= radius as i64 * radius as i64
+            }
+            &Region::Ring {
+                x,
+                z,
+                inner_radius,
+                outer_radius,
+            } => {
+                let (nearest, farthest) = rect.distances_squared(x, z);
+                nearest <= outer_radius as i64 * outer_radius as i64
+                    && farthest >= inner_radius as i64 * inner_radius as i64
+            }
+            Region::Box { .. } | Region::Polygon { .. } => true,
+        }
+    }
+}
+
+/// Even-odd rule. Columns on the outline count as inside.
+fn polygon_contains(points: &[(i32, i32)], x: i32, z: i32) -> bool {
+    let (x, z) = (x as f64, z as f64);
+    let mut inside = false;
+    for (i, &(x1, z1)) in points.iter().enumerate() {
+        let (x2, z2) = points[(i + 1) % points.len()];
+        let (x1, z1, x2, z2) = (x1 as f64, z1 as f64, x2 as f64, z2 as f64);
+        // On the edge
+        let cross = (x2 - x1) * (z - z1) - (z2 - z1) * (x - x1);
+        if cross == 0.0
+            && (x1.min(x2)..=x1.max(x2)).contains(&x)
+            && (z1.min(z2)..=z1.max(z2)).contains(&z)
+        {
+            return true;
+        }
+        if (z1 > z) != (z2 > z) && x < x1 + (z - z1) * (x2 - x1) / (z2 - z1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Evenly spread columns of a rectangle, up to `samples` along each axis.
+fn grid(rect: &Rect, samples: i64) -> impl Iterator<Item = (i32, i32)> {
+    let (width, length) = (
+        (rect.x_max - rect.x_min) as i64 + 1,
+        (rect.z_max - rect.z_min) as i64 + 1,
+    );
+    let (steps_x, steps_z) = (width.min(samples), length.min(samples));
+    let (x_min, z_min) = (rect.x_min as i64, rect.z_min as i64);
+    (0..steps_x).flat_map(move |i| {
+        (0..steps_z).map(move |j| {
+            (
+                (x_min + i * width / steps_x) as i32,
+                (z_min + j * length / steps_z) as i32,
+            )
+        })
+    })
+}
+
+/// The combined area to scan: everything inside any region, but outside all excludes.
+#[derive(Debug, Clone)]
+pub struct Area {
+    pub regions: Vec<Region>,
+    pub exclude: Vec<Region>,
+}
+
+impl Area {
+    /// The rectangle containing all regions.
+    pub fn bounds(&self) -> Rect {
+        let bounds: Vec<Rect> = self.regions.iter().map(Region::bounds).collect();
+        Rect {
+            x_min: bounds.iter().map(|b| b.x_min).min().unwrap(),
+            x_max: bounds.iter().map(|b| b.x_max).max().unwrap(),
+            z_min: bounds.iter().map(|b| b.z_min).min().unwrap(),
+            z_max: bounds.iter().map(|b| b.z_max).max().unwrap(),
+        }
+    }
+
+    /// Whether the area is the same as its bounds, so no column needs to be checked.
+    pub fn is_rect(&self) -> bool {
+        self.exclude.is_empty()
+            && self.regions.len() == 1
+            && matches!(self.regions[0], Region::Box { .. })
+    }
+
+    /// Amount of columns in the area. Exact for a box, otherwise estimated from a grid of
+    /// up to `samples` x `samples` columns.
+    pub fn estimate_columns(&self, samples: i64) -> f64 {
+        self.estimate_columns_in(&self.bounds(), samples)
+    }
+
+    /// Amount of columns of the rectangle inside the area. Exact if the rectangle is fully
+    /// inside or outside, otherwise estimated like [`Self::estimate_columns`].
+    pub fn estimate_columns_in(&self, rect: &Rect, samples: i64) -> f64 {
+        let total = rect.columns() as f64;
+        if self.contains_rect(rect) {
+            return total;
+        }
+        if !self.intersects_rect(rect) {
+            return 0.0;
+        }
+        let grid: Vec<(i32, i32)> = grid(rect, samples).collect();
+        let inside = grid.iter().filter(|&&(x, z)| self.contains(x, z)).count();
+        total * inside as f64 / grid.len() as f64
+    }
+
+    /// A column inside the area, looked for on a grid of up to `samples` x `samples`
+    /// columns.
+    pub fn find_column(&self, samples: i64) -> Option<(i32, i32)> {
+        grid(&self.bounds(), samples).find(|&(x, z)| self.contains(x, z))
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        self.regions.iter().any(|region| region.contains(x, z))
+            && !self.exclude.iter().any(|region| region.contains(x, z))
+    }
+
+    /// Whether every column of the rectangle is inside (can be false for polygons).
+    pub fn contains_rect(&self, rect: &Rect) -> bool {
+        self.regions.iter().any(|region| region.contains_rect(rect))
+            && !self
+                .exclude
+                .iter()
+                .any(|region| region.intersects_rect(rect))
+    }
+
+    /// Whether any column of the rectangle might be inside.
+    pub fn intersects_rect(&self, rect: &Rect) -> bool {
+        self.regions
+            .iter()
+            .any(|region| region.intersects_rect(rect))
+            && !self.exclude.iter().any(|region| region.contains_rect(rect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the rectangle methods against checking every column.
+    fn assert_rect_consistent(area: &Area, rect: Rect) {
+        let inside: Vec<bool> = (rect.x_min..=rect.x_max)
+            .flat_map(|x| (rect.z_min..=rect.z_max).map(move |z| (x, z)))
+            .map(|(x, z)| area.contains(x, z))
+            .collect();
+        if area.contains_rect(&rect) {
+            assert!(inside.iter().all(|&inside| inside), "{rect:?}");
+        }
+        if inside.iter().any(|&inside| inside) {
+            assert!(area.intersects_rect(&rect), "{rect:?}");
+        }
+    }
+
+    #[test]
+    fn rect_checks_match_columns() {
+        let area = Area {
+            regions: vec![
+                Region::Circle {
+                    x: 0,
+                    z: 0,
+                    radius: 40,
+                },
+                Region::Ring {
+                    x: 100,
+                    z: -20,
+                    inner_radius: 10,
+                    outer_radius: 30,
+                },
+                Region::Polygon {
+                    points: vec![(-100, 50), (-60, 90), (-100, 90)],
+                },
+            ],
+            exclude: vec![Region::Box {
+                x_min: -5,
+                x_max: 5,
+                z_min: -5,
+                z_max: 5,
+            }],
+        };
+        for x in (-120..140).step_by(7) {
+            for z in (-60..100).step_by(7) {
+                assert_rect_consistent(
+                    &area,
+                    Rect {
+                        x_min: x,
+                        x_max: x + 9,
+                        z_min: z,
+                        z_max: z + 9,
+                    },
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn estimates_columns() {
+        let square = Region::Box {
+            x_min: -100,
+            x_max: 99,
+            z_min: 0,
+            z_max: 49,
+        };
+        let area = Area {
+            regions: vec![square.clone()],
+            exclude: vec![],
+        };
+        assert_eq!(area.estimate_columns(10), 200.0 * 50.0);
+
+        let circle = Area {
+            regions: vec![Region::Circle {
+                x: 0,
+                z: 0,
+                radius: 500,
+            }],
+            exclude: vec![square],
+        };
+        let expected = std::f64::consts::PI * 500.0 * 500.0 - 200.0 * 50.0;
+        let estimate = circle.estimate_columns(1000);
+        assert!(
+            (estimate - expected).abs() < expected * 0.01,
+            "{estimate} vs {expected}"
+        );
+
+        // Rectangles fully inside or outside are exact
+        let rect = |x_min, z_min| Rect {
+            x_min,
+            x_max: x_min + 9,
+            z_min,
+            z_max: z_min + 9,
+        };
+        assert_eq!(circle.estimate_columns_in(&rect(100, 100), 2), 100.0);
+        assert_eq!(circle.estimate_columns_in(&rect(0, 10), 2), 0.0);
+        assert_eq!(circle.estimate_columns_in(&rect(600, 0), 2), 0.0);
+        // Half of this one is excluded
+        assert_eq!(circle.estimate_columns_in(&rect(95, 0), 10), 50.0);
+    }
+
+    #[test]
+    fn polygon_contains_outline_and_inside() {
+        let points = [(0, 0), (10, 0), (10, 10), (0, 10)];
+        assert!(polygon_contains(&points, 0, 0));
+        assert!(polygon_contains(&points, 10, 5));
+        assert!(polygon_contains(&points, 5, 5));
+        assert!(!polygon_contains(&points, 11, 5));
+        assert!(!polygon_contains(&points, -1, -1));
+    }
+}
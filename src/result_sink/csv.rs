@@ -0,0 +1,108 @@
+use super::{Hit, ResultSink};
+use serde_json::Value;
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+/// Writes every hit as a row of a CSV table. Failed entries are separated by ";".
+pub struct CsvSink<W> {
+    writer: W,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(
+            writer,
+            "x,y,z,facing,fails,failed_entries,biome,provider,score,p_value"
+        )?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write + Send> ResultSink for CsvSink<W> {
+    fn report(&mut self, hit: &Hit) -> io::Result<()> {
+        let failed_entries = hit
+            .failed_entries
+            .iter()
+            .map(|index| index.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            hit.x,
+            hit.y,
+            hit.z,
+            hit.facing,
+            hit.fails,
+            failed_entries,
+            hit.biome.map(|biome| biome.to_string()).unwrap_or_default(),
+            hit.provider,
+            hit.score,
+            hit.p_value,
+        )?;
+        self.writer.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Read hits as written by [`CsvSink`]. Columns are found by the header, empty lines are
+/// skipped.
+pub fn read_csv(path: &Path) -> io::Result<Vec<Hit>> {
+    let reader = io::BufReader::new(std::fs::File::open(path)?);
+    let invalid = |number: usize, err: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Line {}: {err}", number + 1),
+        )
+    };
+    let mut columns: Option<Vec<String>> = None;
+    let mut hits = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let Some(columns) = &columns else {
+            columns = Some(fields.iter().map(|&field| field.to_owned()).collect());
+            continue;
+        };
+        if fields.len() != columns.len() {
+            return Err(invalid(
+                number,
+                format!("Expected {} fields, got {}", columns.len(), fields.len()),
+            ));
+        }
+        // Deserialized like a line of JSON Lines, so both formats read the same
+        let object = columns
+            .iter()
+            .zip(fields)
+            .map(|(column, field)| (column.clone(), field_value(column, field)))
+            .collect();
+        let hit = serde_json::from_value(Value::Object(object))
+            .map_err(|err| invalid(number, err.to_string()))?;
+        hits.push(hit);
+    }
+    Ok(hits)
+}
+
+/// The JSON value of a field: numbers stay numbers, failed entries become a list and an
+/// empty field (e.g. no biome) is null.
+fn field_value(column: &str, field: &str) -> Value {
+    let value =
+        |text: &str| serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()));
+    match field {
+        _ if column == "failed_entries" => field
+            .split(';')
+            .filter(|index| !index.is_empty())
+            .map(value)
+            .collect(),
+        "" => Value::Null,
+        _ => value(field),
+    }
+}
@@ -0,0 +1,49 @@
+use super::{Hit, ResultSink};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+/// Writes every hit as a JSON object on its own line.
+pub struct JsonLinesSink<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write + Send> ResultSink for JsonLinesSink<W> {
+    fn report(&mut self, hit: &Hit) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, hit)?;
+        self.writer.write_all(b"\n")?;
+        // Hits are rare and should survive a crash
+        self.writer.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Read hits as written by [`JsonLinesSink`]. Empty lines are skipped.
+pub fn read_json_lines(path: &Path) -> io::Result<Vec<Hit>> {
+    let reader = io::BufReader::new(std::fs::File::open(path)?);
+    let mut hits = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let hit = serde_json::from_str(&line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Line {}: {err}", number + 1),
+            )
+        })?;
+        hits.push(hit);
+    }
+    Ok(hits)
+}
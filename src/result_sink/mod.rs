@@ -0,0 +1,264 @@
+mod csv;
+mod jsonl;
+
+pub use self::csv::CsvSink;
+pub use jsonl::JsonLinesSink;
+
+use crate::facing::Facing;
+use cubiomes::finders::BiomeID;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// A position where the formation matched (possibly with some failures).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hit {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub facing: Facing,
+    pub fails: usize,
+    /// Indices into the formation of all entries that didn't match
+    pub failed_entries: Vec<usize>,
+    pub biome: Option<BiomeID>,
+    pub provider: String,
+    /// Sum of the confidences of the failed entries (lower is better)
+    #[serde(default)]
+    pub score: f64,
+    /// Chance that a random position scores as well
+    #[serde(default)]
+    pub p_value: f64,
+}
+
+impl Hit {
+    /// Everything but the score and p-value, which follow from the failed entries.
+    fn key(&self) -> (i32, i32, i32, Facing, &[usize], Option<BiomeID>, &str) {
+        (
+            self.x,
+            self.y,
+            self.z,
+            self.facing,
+            &self.failed_entries,
+            self.biome,
+            &self.provider,
+        )
+    }
+}
+
+impl PartialEq for Hit {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Hit {}
+
+impl Hash for Hit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// Something that hits get reported into.
+pub trait ResultSink: Send {
+    fn report(&mut self, hit: &Hit) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Create a sink writing into a new file. The format is picked by the extension
+/// (".csv" for CSV, JSON Lines otherwise).
+pub fn create_file_sink(path: &Path) -> io::Result<Box<dyn ResultSink>> {
+    let file = io::BufWriter::new(std::fs::File::create(path)?);
+    Ok(match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => Box::new(CsvSink::new(file)?),
+        _ => Box::new(JsonLinesSink::new(file)),
+    })
+}
+
+/// Read hits from a file as written by the sink of [`create_file_sink`], in the format
+/// picked the same way.
+pub fn read_hits(path: &Path) -> io::Result<Vec<Hit>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => csv::read_csv(path),
+        _ => jsonl::read_json_lines(path),
+    }
+}
+
+/// All sinks of a scan. Shared between the workers.
+#[derive(Default)]
+pub struct Results {
+    sinks: Mutex<Vec<Box<dyn ResultSink>>>,
+    /// Hits that were already reported before (e.g. when resuming a scan)
+    known_hits: Mutex<HashSet<Hit>>,
+    /// Don't report any more hits after this many new ones
+    max_new_hits: Option<u64>,
+    new_hits: AtomicU64,
+}
+
+impl Results {
+    pub fn new(sinks: Vec<Box<dyn ResultSink>>, known_hits: HashSet<Hit>) -> Self {
+        Self {
+            sinks: Mutex::new(sinks),
+            known_hits: Mutex::new(known_hits),
+            max_new_hits: None,
+            new_hits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_max_new_hits(mut self, max_new_hits: Option<u64>) -> Self {
+        self.max_new_hits = max_new_hits;
+        self
+    }
+
+    /// Whether `max_new_hits` were reported, so further hits get dropped.
+    pub fn limit_reached(&self) -> bool {
+        self.max_new_hits
+            .is_some_and(|max| self.new_hits.load(Ordering::Relaxed) >= max)
+    }
+
+    /// Report a hit to all sinks. Returns false if the hit was already known or the
+    /// limit of new hits is reached.
+    pub fn report(&self, hit: &Hit) -> bool {
+        if self.known_hits.lock().unwrap().contains(hit) {
+            return false;
+        }
+        // Holding the lock of the sinks, so workers can't report past the limit together
+        let mut sinks = self.sinks.lock().unwrap();
+        if self.limit_reached() {
+            return false;
+        }
+        for sink in sinks.iter_mut() {
+            if let Err(err) = sink.report(hit) {
+                log::error!("Failed to report hit: {err}");
+            }
+        }
+        self.new_hits.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    pub fn flush(&self) {
+        for sink in self.sinks.lock().unwrap().iter_mut() {
+            if let Err(err) = sink.flush() {
+                log::error!("Failed to flush results: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl Hit {
+    /// A hit without failures, for tests.
+    pub fn for_test(x: i32, y: i32, z: i32, facing: Facing) -> Self {
+        Self {
+            x,
+            y,
+            z,
+            facing,
+            fails: 0,
+            failed_entries: vec![],
+            biome: None,
+            provider: "Vanilla".to_owned(),
+            score: 0.0,
+            p_value: 0.0,
+        }
+    }
+}
+
+/// Keeps the reported hits, so tests can look at them.
+#[cfg(test)]
+pub struct CollectSink(pub std::sync::Arc<Mutex<Vec<Hit>>>);
+
+#[cfg(test)]
+impl ResultSink for CollectSink {
+    fn report(&mut self, hit: &Hit) -> io::Result<()> {
+        self.0.lock().unwrap().push(hit.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_path::TempPath;
+    use std::sync::Arc;
+
+    #[test]
+    fn known_hits_dont_count_towards_the_limit() {
+        let hit = |x| Hit::for_test(x, 64, 0, Facing::North);
+        let reported = Arc::new(Mutex::new(vec![]));
+        let known = HashSet::from([hit(1), hit(2)]);
+        let results = Results::new(vec![Box::new(CollectSink(reported.clone()))], known)
+            .with_max_new_hits(Some(2));
+        assert!(!results.limit_reached());
+        assert!(!results.report(&hit(1)));
+        assert!(results.report(&hit(3)));
+        // Known hits are dropped without using up the limit
+        assert!(!results.report(&hit(2)));
+        assert!(!results.limit_reached());
+        assert!(results.report(&hit(4)));
+        assert!(results.limit_reached());
+        assert!(!results.report(&hit(5)));
+        let xs: Vec<i32> = reported.lock().unwrap().iter().map(|hit| hit.x).collect();
+        assert_eq!(xs, vec![3, 4]);
+
+        let unlimited = Results::new(vec![], HashSet::new());
+        assert!((0..10).all(|x| unlimited.report(&hit(x))));
+        assert!(!unlimited.limit_reached());
+    }
+
+    #[test]
+    fn reads_hits_back_from_both_formats() {
+        let hits = [
+            Hit {
+                x: -120,
+                y: 64,
+                z: 3,
+                facing: Facing::West,
+                fails: 2,
+                failed_entries: vec![1, 4],
+                biome: None,
+                provider: "Vanilla".to_owned(),
+                score: 1.5,
+                p_value: 0.001953125,
+            },
+            Hit {
+                x: 7,
+                y: -30,
+                z: -2000,
+                facing: Facing::North,
+                fails: 0,
+                failed_entries: vec![],
+                biome: Some(4),
+                provider: "Sodium".to_owned(),
+                score: 0.0,
+                p_value: 1e-9,
+            },
+        ];
+        for extension in ["csv", "jsonl"] {
+            let path = TempPath::new(extension);
+            let mut sink = create_file_sink(&path).unwrap();
+            for hit in &hits {
+                sink.report(hit).unwrap();
+            }
+            drop(sink);
+            let read = read_hits(&path).unwrap();
+            assert_eq!(read, hits, "{extension}");
+            for (read, hit) in read.iter().zip(&hits) {
+                assert_eq!((read.score, read.p_value), (hit.score, hit.p_value));
+            }
+        }
+    }
+}
@@ -1,31 +1,342 @@
-use serde::Deserialize;
+use crate::facing::Facing;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The face of a block a rotation was read from.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Face {
+    #[serde(alias = "top")]
+    Up,
+    #[serde(alias = "bottom")]
+    Down,
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Face {
+    pub const fn is_side(self) -> bool {
+        !matches!(self, Face::Up | Face::Down)
+    }
+
+    /// Amount of distinct rotation values that can be observed on this face.
+    pub const fn modulo(self) -> i32 {
+        if self.is_side() {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// The face as it appears in the world when the formation is turned for the given facing.
+    pub const fn turned(self, facing: Facing) -> Self {
+        const HORIZONTAL: [Face; 4] = [Face::North, Face::East, Face::South, Face::West];
+        let index = match self {
+            Face::Up | Face::Down => return self,
+            Face::North => 0,
+            Face::East => 1,
+            Face::South => 2,
+            Face::West => 3,
+        };
+        HORIZONTAL[(index + facing.quarter_turns() as usize) % 4]
+    }
+}
+
+impl fmt::Display for Face {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Face::Up => "up",
+            Face::Down => "down",
+            Face::North => "north",
+            Face::South => "south",
+            Face::East => "east",
+            Face::West => "west",
+        })
+    }
+}
+
+/// The rotations an entry may have, as a bitmask (bit n set means rotation n is allowed).
+///
+/// Written in the config as a single rotation (`1`), a list of possible ones (`[1, 3]`),
+/// all but one (`"!0"`) or `"unknown"` (also `"*"` and `"?"`) if it can't be told at all.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "RotationsConfig")]
+pub struct Rotations(u8);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RotationsConfig {
+    Single(i32),
+    List(Vec<i32>),
+    Text(String),
+}
+
+impl TryFrom<RotationsConfig> for Rotations {
+    type Error = String;
+
+    fn try_from(config: RotationsConfig) -> Result<Self, Self::Error> {
+        let single = |rotation: i32| {
+            if (0..4).contains(&rotation) {
+                Ok(Rotations::single(rotation))
+            } else {
+                Err(format!("{rotation} is not a rotation (0 to 3)"))
+            }
+        };
+        let parse = |text: &str| {
+            let rotation = text
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| format!("\"{text}\" is not a rotation"))?;
+            single(rotation)
+        };
+        match config {
+            RotationsConfig::Single(rotation) => single(rotation),
+            RotationsConfig::List(rotations) if rotations.is_empty() => {
+                Err("A list of rotations needs at least one rotation".to_owned())
+            }
+            RotationsConfig::List(rotations) => {
+                rotations.into_iter().try_fold(Rotations(0), |all, one| {
+                    Ok(Rotations(all.0 | single(one)?.0))
+                })
+            }
+            RotationsConfig::Text(text) => match text.trim() {
+                "unknown" | "*" | "?" => Ok(Rotations::ANY),
+                text => match text.strip_prefix('!') {
+                    // Which rotations are left depends on the face, see `reduced`
+                    Some(rotation) => Ok(Rotations(parse(rotation)?.0 | Rotations::ALL_BUT)),
+                    None => parse(text),
+                },
+            },
+        }
+    }
+}
+
+impl Rotations {
+    pub const ANY: Self = Rotations(0b1111);
+    /// Marks all but the given rotation, until the modulo of the face is known
+    const ALL_BUT: u8 = 0b1000_0000;
+
+    pub const fn single(rotation: i32) -> Self {
+        assert!(0 <= rotation && rotation < 4, "Rotations are 0 to 3");
+        Rotations(1 << rotation)
+    }
+
+    /// Whether a rotation as returned by the texture providers is allowed.
+    #[inline(always)]
+    pub const fn allows(self, rotation: i32) -> bool {
+        (self.0 >> rotation) & 1 != 0
+    }
+
+    /// Whether every rotation that can be observed with the given modulo is allowed.
+    pub const fn allows_all(self, modulo: i32) -> bool {
+        let all = (1 << modulo) - 1;
+        self.0 & all == all
+    }
+
+    /// The allowed rotations as observed with the given modulo (e.g. 3 becomes 1 on sides).
+    /// All but a rotation means all but the one it is observed as.
+    pub const fn reduced(self, modulo: i32) -> Self {
+        let all = (1 << modulo) - 1;
+        let reduced = if modulo == 2 {
+            (self.0 | (self.0 >> 2)) & 0b11
+        } else {
+            self.0 & 0b1111
+        };
+        if self.0 & Self::ALL_BUT != 0 {
+            Rotations(!reduced & all)
+        } else {
+            Rotations(reduced)
+        }
+    }
+
+    /// Add `quarter_turns` to every allowed rotation of a top or bottom face.
+    pub const fn turned(self, quarter_turns: i32) -> Self {
+        let quarter_turns = quarter_turns.rem_euclid(4);
+        Rotations(((self.0 << quarter_turns) | (self.0 >> (4 - quarter_turns))) & 0b1111)
+    }
+
+    /// Whether any rotation is allowed by both.
+    pub const fn overlaps(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// All allowed rotations, lowest first.
+    pub fn iter(self) -> impl Iterator<Item = i32> {
+        (0..4).filter(move |&rotation| self.allows(rotation))
+    }
+}
+
+impl fmt::Display for Rotations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Rotations::ANY {
+            return f.write_str("unknown");
+        }
+        let rotations: Vec<String> = self.iter().map(|rotation| rotation.to_string()).collect();
+        f.write_str(&rotations.join(" or "))
+    }
+}
 
 /// Important: Call fix_rotation if not using new()!
 /// Otherwise the rotation value will not be adjusted and wrong!
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "RotationInfoConfig")]
 pub struct RotationInfo {
     pub x: i32,
     pub y: i32,
     pub z: i32,
-    pub rotation: i32,
-    pub is_side: bool,
+    pub rotation: Rotations,
+    pub face: Face,
+}
+
+/// A formation entry as written in the config. Older configs give `is_side` instead of
+/// the face, which is read as a north face (all sides show the same values) or the top.
+#[derive(Deserialize)]
+struct RotationInfoConfig {
+    x: i32,
+    y: i32,
+    z: i32,
+    rotation: Rotations,
+    face: Option<Face>,
+    is_side: Option<bool>,
+}
+
+impl TryFrom<RotationInfoConfig> for RotationInfo {
+    type Error = String;
+
+    fn try_from(config: RotationInfoConfig) -> Result<Self, Self::Error> {
+        let face = match (config.face, config.is_side) {
+            (Some(face), None) => face,
+            (Some(face), Some(is_side)) if face.is_side() == is_side => face,
+            (Some(face), Some(_)) => return Err(format!("The {face} face doesn't fit is_side")),
+            (None, Some(true)) => Face::North,
+            (None, Some(false)) => Face::Up,
+            (None, None) => return Err("Formation entries need a face (e.g. face = \"up\")".into()),
+        };
+        Ok(Self {
+            x: config.x,
+            y: config.y,
+            z: config.z,
+            rotation: config.rotation,
+            face,
+        })
+    }
 }
 
 impl RotationInfo {
     #[allow(dead_code)]
-    pub const fn new(x: i32, y: i32, z: i32, rotation: i32, is_side: bool) -> Self {
+    pub const fn new(x: i32, y: i32, z: i32, rotation: i32, face: Face) -> Self {
         Self {
             x,
             y,
             z,
-            rotation: if is_side { rotation % 2 } else { rotation },
-            is_side,
+            rotation: Rotations::single(rotation).reduced(face.modulo()),
+            face,
         }
     }
 
     pub fn fix_rotation(&mut self) {
-        if self.is_side {
-            self.rotation %= 2;
+        self.rotation = self.rotation.reduced(self.face.modulo());
+    }
+
+    /// Whether the entry matches a block with the given rotation (as shown on its top).
+    ///
+    /// All faces of a block are textured from the same random value: tops and bottoms show
+    /// it modulo 4, sides modulo 2. So the entries of one block don't fail independently,
+    /// and a side never tells more than a known top of the same block.
+    pub const fn allows_block(&self, rotation: i32) -> bool {
+        self.rotation.allows(rotation % self.face.modulo())
+    }
+
+    /// Whether the entry can't fail, because every rotation is allowed.
+    pub const fn is_unknown(&self) -> bool {
+        self.rotation.allows_all(self.face.modulo())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(config: RotationsConfig) -> Rotations {
+        Rotations::try_from(config).unwrap()
+    }
+
+    #[test]
+    fn rotations_from_config() {
+        assert_eq!(parse(RotationsConfig::Single(2)), Rotations(0b0100));
+        assert_eq!(parse(RotationsConfig::List(vec![1, 3])), Rotations(0b1010));
+        assert_eq!(
+            parse(RotationsConfig::Text("!0".to_owned())).reduced(4),
+            Rotations(0b1110)
+        );
+        // On a side, all but 0 (or 2, which looks the same) leaves 1
+        let all_but = |text: &str| parse(RotationsConfig::Text(text.to_owned()));
+        assert_eq!(all_but("!0").reduced(2), Rotations(0b10));
+        assert_eq!(all_but("!2").reduced(2), Rotations(0b10));
+        assert_eq!(all_but("!3").reduced(2), Rotations(0b01));
+        assert!(Rotations::try_from(RotationsConfig::Single(4)).is_err());
+        assert!(Rotations::try_from(RotationsConfig::Single(-1)).is_err());
+        assert!(Rotations::try_from(RotationsConfig::List(vec![1, 5])).is_err());
+        assert!(Rotations::try_from(RotationsConfig::Text("!4".to_owned())).is_err());
+        assert_eq!(parse(RotationsConfig::Text("*".to_owned())), Rotations::ANY);
+        assert!(Rotations::try_from(RotationsConfig::List(vec![])).is_err());
+        assert!(Rotations::try_from(RotationsConfig::Text("one".to_owned())).is_err());
+    }
+
+    #[test]
+    fn entries_from_config() {
+        let entry = |face, is_side| RotationInfoConfig {
+            x: 1,
+            y: 2,
+            z: 3,
+            rotation: Rotations::single(1),
+            face,
+            is_side,
+        };
+        let face = |config| RotationInfo::try_from(config).map(|info| info.face);
+        assert_eq!(face(entry(Some(Face::South), None)), Ok(Face::South));
+        // Older configs only tell whether it is a side
+        assert_eq!(face(entry(None, Some(true))), Ok(Face::North));
+        assert_eq!(face(entry(None, Some(false))), Ok(Face::Up));
+        assert_eq!(face(entry(Some(Face::Down), Some(false))), Ok(Face::Down));
+        assert!(face(entry(Some(Face::Up), Some(true))).is_err());
+        assert!(face(entry(None, None)).is_err());
+    }
+
+    #[test]
+    fn reduced_and_turned() {
+        // 1 or 3 look the same on a side
+        assert_eq!(Rotations(0b1010).reduced(2), Rotations(0b10));
+        assert!(!Rotations(0b0101).reduced(2).allows_all(2));
+        assert!(Rotations(0b0011).reduced(2).allows_all(2));
+        assert_eq!(Rotations(0b1010).turned(1), Rotations(0b0101));
+        assert_eq!(Rotations(0b1000).turned(1), Rotations(0b0001));
+        assert_eq!(Rotations(0b0001).turned(-1), Rotations(0b1000));
+        assert_eq!(Rotations(0b0110).turned(4), Rotations(0b0110));
+    }
+
+    #[test]
+    fn faces_turn_with_the_facing() {
+        // The direction each side faces to, in the formation and the world alike
+        let normals = [
+            (Face::North, (0, -1)),
+            (Face::East, (1, 0)),
+            (Face::South, (0, 1)),
+            (Face::West, (-1, 0)),
+        ];
+        let normal = |face: Face| normals.iter().find(|(f, _)| *f == face).unwrap().1;
+        for facing in Facing::ALL {
+            for (face, (x, z)) in normals {
+                assert_eq!(
+                    normal(face.turned(facing)),
+                    facing.turn(x, z),
+                    "{face} {facing}"
+                );
+            }
+            assert_eq!(Face::Up.turned(facing), Face::Up);
+            assert_eq!(Face::Down.turned(facing), Face::Down);
         }
     }
 }
@@ -0,0 +1,476 @@
+use crate::region::Rect;
+use serde::Deserialize;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// A rectangular part of the search area. Bounds are inclusive.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Tile {
+    /// Stable index of this tile (for the same area and tile size)
+    pub index: u64,
+    pub x_min: i32,
+    pub x_max: i32,
+    pub z_min: i32,
+    pub z_max: i32,
+}
+
+impl Tile {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x_min: self.x_min,
+            x_max: self.x_max,
+            z_min: self.z_min,
+            z_max: self.z_max,
+        }
+    }
+}
+
+/// A place the formation is likely to be near.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+pub struct Hotspot {
+    pub x: i32,
+    pub z: i32,
+    /// Standard deviation of the distance in blocks
+    pub sigma: f64,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// The order tiles are scanned in (roughly, as workers run in parallel).
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ScanOrder {
+    /// Nearest tiles to a point first, spiraling outwards
+    Spiral { x: i32, z: i32 },
+    /// Most likely tiles first, given a Gaussian around each hotspot
+    Density { hotspots: Vec<Hotspot> },
+}
+
+impl ScanOrder {
+    /// Check that every hotspot has a usable Gaussian.
+    pub fn check(&self) -> Result<(), String> {
+        let ScanOrder::Density { hotspots } = self else {
+            return Ok(());
+        };
+        for spot in hotspots {
+            if !spot.sigma.is_finite() || spot.sigma <= 0.0 {
+                return Err(format!(
+                    "The sigma of the hotspot at {}, {} has to be above 0",
+                    spot.x, spot.z
+                ));
+            }
+            if !spot.weight.is_finite() || spot.weight < 0.0 {
+                return Err(format!(
+                    "The weight of the hotspot at {}, {} can't be negative",
+                    spot.x, spot.z
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Tiles with a smaller key are scanned first.
+    fn key(&self, tile: &Tile) -> (f64, f64) {
+        let center_x = (tile.x_min as f64 + tile.x_max as f64) / 2.0;
+        let center_z = (tile.z_min as f64 + tile.z_max as f64) / 2.0;
+        let distance = |x: i32, z: i32| (center_x - x as f64).hypot(center_z - z as f64);
+        match self {
+            &ScanOrder::Spiral { x, z } => (
+                distance(x, z),
+                (center_z - z as f64).atan2(center_x - x as f64),
+            ),
+            ScanOrder::Density { hotspots } => {
+                let density: f64 = hotspots
+                    .iter()
+                    .map(|spot| {
+                        let distance = distance(spot.x, spot.z) / spot.sigma;
+                        spot.weight * (-0.5 * distance * distance).exp()
+                    })
+                    .sum();
+                // Far away from all hotspots the density is zero, so go by distance there
+                let nearest = hotspots
+                    .iter()
+                    .map(|spot| distance(spot.x, spot.z) / spot.sigma)
+                    .fold(f64::INFINITY, f64::min);
+                (-density, nearest)
+            }
+        }
+    }
+}
+
+/// The axis along which the tiles are walked first.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SplitAxis {
+    X,
+    Z,
+}
+
+/// Hands out tiles of the search area to the workers.
+///
+/// Every worker starts with its own contiguous chunk of tiles. Workers that run out of
+/// tiles steal from the end of the chunk of another worker, so nobody sits idle while a
+/// slow part of the area is still being scanned.
+///
+/// With a [`ScanOrder`], the tiles are sorted and dealt out to the workers in turn
+/// instead, so all workers start with the first tiles of the order.
+pub struct Scheduler {
+    pub split_axis: SplitAxis,
+    tiles: Vec<Tile>,
+    queues: Vec<Mutex<VecDeque<usize>>>,
+}
+
+impl Scheduler {
+    /// Split the area into tiles of `tile_size` x `tile_size` columns (smaller at the
+    /// edges). The tiles lie on one grid over the bounds of all `parts`, but only the
+    /// ones overlapping a part are created. Tiles for which `skip` returns true are never
+    /// handed out.
+    pub fn new(
+        parts: &[Rect],
+        tile_size: i32,
+        workers: usize,
+        order: Option<&ScanOrder>,
+        skip: impl Fn(&Tile) -> bool,
+    ) -> Self {
+        assert!(!parts.is_empty() && tile_size > 0 && workers > 0);
+        assert!(parts
+            .iter()
+            .all(|part| part.x_min <= part.x_max && part.z_min <= part.z_max));
+        let x_min = parts.iter().map(|part| part.x_min).min().unwrap();
+        let x_max = parts.iter().map(|part| part.x_max).max().unwrap();
+        let z_min = parts.iter().map(|part| part.z_min).min().unwrap();
+        let z_max = parts.iter().map(|part| part.z_max).max().unwrap();
+
+        // Walk along the wider axis first. Chunks of a narrow range are then bands
+        // across all of it, instead of a few tiles in a line.
+        let split_axis = if x_max - x_min >= z_max - z_min {
+            SplitAxis::X
+        } else {
+            SplitAxis::Z
+        };
+        let ((major_min, major_max), (minor_min, minor_max)) = match split_axis {
+            SplitAxis::X => ((x_min, x_max), (z_min, z_max)),
+            SplitAxis::Z => ((z_min, z_max), (x_min, x_max)),
+        };
+        let cell = |min: i32, value: i32| (value as i64 - min as i64) as u64 / tile_size as u64;
+        let minor_count = cell(minor_min, minor_max) + 1;
+
+        // Tiles are numbered along the grid, so their index doesn't depend on the parts
+        let mut indices = vec![];
+        for part in parts {
+            let (major, minor) = match split_axis {
+                SplitAxis::X => ((part.x_min, part.x_max), (part.z_min, part.z_max)),
+                SplitAxis::Z => ((part.z_min, part.z_max), (part.x_min, part.x_max)),
+            };
+            for major in cell(major_min, major.0)..=cell(major_min, major.1) {
+                let minor = cell(minor_min, minor.0)..=cell(minor_min, minor.1);
+                indices.extend(minor.map(|minor| major * minor_count + minor));
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let span = |min: i32, max: i32, cell: u64| {
+            let start = min as i64 + (cell * tile_size as u64) as i64;
+            (
+                start as i32,
+                (start + tile_size as i64 - 1).min(max as i64) as i32,
+            )
+        };
+        let tiles: Vec<Tile> = indices
+            .into_iter()
+            .map(|index| {
+                let major = span(major_min, major_max, index / minor_count);
+                let minor = span(minor_min, minor_max, index % minor_count);
+                let ((x_min, x_max), (z_min, z_max)) = match split_axis {
+                    SplitAxis::X => (major, minor),
+                    SplitAxis::Z => (minor, major),
+                };
+                Tile {
+                    index,
+                    x_min,
+                    x_max,
+                    z_min,
+                    z_max,
+                }
+            })
+            .collect();
+
+        let mut pending: Vec<usize> = (0..tiles.len()).filter(|&i| !skip(&tiles[i])).collect();
+        let queues = if let Some(order) = order {
+            // The sort is stable, so the order is the same on every run (and on resume)
+            pending.sort_by(|&a, &b| {
+                let (a, b) = (order.key(&tiles[a]), order.key(&tiles[b]));
+                a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+            });
+            (0..workers)
+                .map(|worker| {
+                    Mutex::new(
+                        pending
+                            .iter()
+                            .skip(worker)
+                            .step_by(workers)
+                            .copied()
+                            .collect(),
+                    )
+                })
+                .collect()
+        } else {
+            let chunk_size = pending.len().div_ceil(workers);
+            (0..workers)
+                .map(|worker| {
+                    let start = (worker * chunk_size).min(pending.len());
+                    let end = (start + chunk_size).min(pending.len());
+                    Mutex::new(pending[start..end].iter().copied().collect())
+                })
+                .collect()
+        };
+
+        Self {
+            split_axis,
+            tiles,
+            queues,
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// All tiles that are still going to be handed out.
+    pub fn pending_tiles(&self) -> Vec<Tile> {
+        self.queues
+            .iter()
+            .flat_map(|queue| {
+                let queue = queue.lock().unwrap();
+                queue.iter().map(|&i| self.tiles[i]).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Get the next tile for the given worker. Returns None once all tiles are handed out.
+    pub fn next(&self, worker: usize) -> Option<Tile> {
+        if let Some(i) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(self.tiles[i]);
+        }
+        // Steal from the back of the fullest queue. Queues never grow, so once the
+        // fullest one is empty, everything has been handed out.
+        loop {
+            let (len, victim) = self
+                .queues
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != worker)
+                .map(|(_, queue)| (queue.lock().unwrap().len(), queue))
+                .max_by_key(|&(len, _)| len)?;
+            if len == 0 {
+                return None;
+            }
+            let stolen = victim.lock().unwrap().pop_back();
+            if let Some(i) = stolen {
+                return Some(self.tiles[i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// Let some workers drain the scheduler and count how often each column was handed out.
+    fn scan_all(scheduler: Scheduler, workers: usize) -> HashMap<(i32, i32), u32> {
+        let scheduler = Arc::new(scheduler);
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let scheduler = Arc::clone(&scheduler);
+                std::thread::spawn(move || {
+                    let mut tiles = vec![];
+                    while let Some(tile) = scheduler.next(worker) {
+                        tiles.push(tile);
+                    }
+                    tiles
+                })
+            })
+            .collect();
+
+        let mut columns = HashMap::new();
+        for handle in handles {
+            for tile in handle.join().unwrap() {
+                for x in tile.x_min..=tile.x_max {
+                    for z in tile.z_min..=tile.z_max {
+                        *columns.entry((x, z)).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        columns
+    }
+
+    fn rect((x_min, x_max): (i32, i32), (z_min, z_max): (i32, i32)) -> Rect {
+        Rect {
+            x_min,
+            x_max,
+            z_min,
+            z_max,
+        }
+    }
+
+    fn assert_exactly_once(x: (i32, i32), z: (i32, i32), tile_size: i32, workers: usize) {
+        let columns = scan_all(
+            Scheduler::new(&[rect(x, z)], tile_size, workers, None, |_| false),
+            workers,
+        );
+        let expected = (x.1 - x.0 + 1) as usize * (z.1 - z.0 + 1) as usize;
+        assert_eq!(
+            columns.len(),
+            expected,
+            "x {x:?}, z {z:?}, tile size {tile_size}"
+        );
+        for (&(cx, cz), &count) in &columns {
+            assert!((x.0..=x.1).contains(&cx) && (z.0..=z.1).contains(&cz));
+            assert_eq!(count, 1, "column {cx}, {cz} scanned {count} times");
+        }
+    }
+
+    #[test]
+    fn every_column_exactly_once() {
+        assert_exactly_once((-100, 100), (-50, 75), 16, 4);
+        assert_exactly_once((0, 0), (0, 0), 16, 3);
+        assert_exactly_once((-3, 2), (-1000, 1000), 64, 8);
+        assert_exactly_once((-1000, 999), (5, 9), 7, 5);
+        assert_exactly_once((10, 40), (10, 40), 100, 16);
+    }
+
+    #[test]
+    fn only_tiles_of_the_parts_are_created() {
+        // Two small parts near opposite corners of a large area
+        let parts = [
+            rect((-1_000_000, -999_990), (-1_000_000, -999_990)),
+            rect((999_990, 1_000_000), (999_990, 1_000_000)),
+        ];
+        let scheduler = Scheduler::new(&parts, 256, 2, None, |_| false);
+        assert!(scheduler.tile_count() <= 8);
+        let columns = scan_all(scheduler, 2);
+        assert!(columns.values().all(|&count| count == 1));
+        for part in parts {
+            for (x, z) in [(part.x_min, part.z_min), (part.x_max, part.z_max)] {
+                assert!(columns.contains_key(&(x, z)));
+            }
+        }
+
+        // Overlapping parts share their tiles, which are numbered like on the whole grid
+        let whole = Scheduler::new(&[rect((0, 99), (0, 49))], 10, 1, None, |_| false);
+        let parts = [
+            rect((0, 39), (0, 49)),
+            rect((20, 99), (10, 19)),
+            rect((95, 99), (45, 49)),
+        ];
+        let scheduler = Scheduler::new(&parts, 10, 1, None, |_| false);
+        assert_eq!(scheduler.tile_count(), 20 + 6 + 1);
+        for tile in scheduler.pending_tiles() {
+            assert_eq!(whole.tiles[tile.index as usize], tile);
+        }
+    }
+
+    #[test]
+    fn narrow_x_range_splits_along_z() {
+        let scheduler = Scheduler::new(&[rect((0, 9), (-500, 500))], 32, 4, None, |_| false);
+        assert_eq!(scheduler.split_axis, SplitAxis::Z);
+        // Every worker gets a part of the long axis
+        for worker in 0..4 {
+            assert!(!scheduler.queues[worker].lock().unwrap().is_empty());
+        }
+        let scheduler = Scheduler::new(&[rect((-500, 500), (0, 9))], 32, 4, None, |_| false);
+        assert_eq!(scheduler.split_axis, SplitAxis::X);
+    }
+
+    #[test]
+    fn spiral_starts_at_the_hint() {
+        let order = ScanOrder::Spiral { x: 500, z: -300 };
+        let scheduler = Scheduler::new(
+            &[rect((-1000, 999), (-1000, 999))],
+            200,
+            3,
+            Some(&order),
+            |_| false,
+        );
+        let first = scheduler.next(0).unwrap();
+        assert!((first.x_min..=first.x_max).contains(&500));
+        assert!((first.z_min..=first.z_max).contains(&-300));
+        // Everything still gets handed out exactly once
+        let columns = scan_all(scheduler, 3);
+        assert_eq!(columns.len() as u64 + first.rect().columns(), 2000 * 2000);
+        assert!(columns.values().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn density_prefers_the_likelier_hotspot() {
+        let hotspot = |x, weight| Hotspot {
+            x,
+            z: 0,
+            sigma: 200.0,
+            weight,
+        };
+        let order = ScanOrder::Density {
+            hotspots: vec![hotspot(-800, 1.0), hotspot(800, 2.0)],
+        };
+        let scheduler = Scheduler::new(
+            &[rect((-1000, 999), (-100, 99))],
+            100,
+            1,
+            Some(&order),
+            |_| false,
+        );
+        let first = scheduler.next(0).unwrap();
+        assert!((first.x_min..=first.x_max).contains(&800));
+    }
+
+    #[test]
+    fn hotspots_need_a_positive_sigma_and_weight() {
+        let order = |sigma, weight| ScanOrder::Density {
+            hotspots: vec![Hotspot {
+                x: 0,
+                z: 0,
+                sigma,
+                weight,
+            }],
+        };
+        assert!(order(200.0, 0.0).check().is_ok());
+        assert!(order(0.0, 1.0).check().is_err());
+        assert!(order(-5.0, 1.0).check().is_err());
+        assert!(order(f64::NAN, 1.0).check().is_err());
+        assert!(order(f64::INFINITY, 1.0).check().is_err());
+        assert!(order(200.0, -1.0).check().is_err());
+        assert!(order(200.0, f64::NAN).check().is_err());
+        assert!(ScanOrder::Spiral { x: 0, z: 0 }.check().is_ok());
+    }
+
+    #[test]
+    fn skipped_tiles_are_not_handed_out() {
+        let scheduler = Scheduler::new(&[rect((0, 99), (0, 99))], 10, 2, None, |tile| {
+            tile.index % 2 == 0
+        });
+        assert_eq!(
+            scheduler
+                .pending_tiles()
+                .iter()
+                .map(|tile| tile.rect().columns())
+                .sum::<u64>(),
+            50 * 100
+        );
+        let mut handed_out = vec![];
+        while let Some(tile) = scheduler.next(1) {
+            handed_out.push(tile.index);
+        }
+        handed_out.sort_unstable();
+        assert_eq!(
+            handed_out,
+            (0..100).filter(|i| i % 2 == 1).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::rotation_info::RotationInfo;
+
+/// Scores are computed in steps of this fraction, so their distribution can be tabulated
+const STEPS_PER_POINT: f64 = 1000.0;
+
+/// Scores positions by the confidence in their failed entries. Lower is better.
+///
+/// Every failed entry adds its confidence to the score, so without confidences the score
+/// is the amount of failures. To tell real matches from near-misses, a score is turned into
+/// the chance that a random position scores as well. The entries of a block are taken
+/// together (see [`RotationInfo::allows_block`]), blocks are assumed to be independent.
+#[derive(Debug, Clone)]
+pub struct Scoring {
+    /// Confidence of every entry, in steps
+    steps: Vec<usize>,
+    /// Chance of a random position to score at most the index (in steps)
+    cumulative: Vec<f64>,
+}
+
+/// Indices of the entries on each block, in the order the blocks first appear.
+pub fn blocks(entries: &[RotationInfo]) -> Vec<Vec<usize>> {
+    let mut blocks: Vec<Vec<usize>> = vec![];
+    for (index, entry) in entries.iter().enumerate() {
+        let same_block = |block: &&mut Vec<usize>| {
+            let other = &entries[block[0]];
+            (other.x, other.y, other.z) == (entry.x, entry.y, entry.z)
+        };
+        match blocks.iter_mut().find(same_block) {
+            Some(block) => block.push(index),
+            None => blocks.push(vec![index]),
+        }
+    }
+    blocks
+}
+
+impl Scoring {
+    /// Score with every rotation being equally likely.
+    pub fn new(entries: &[RotationInfo], confidences: &[f64]) -> Self {
+        Self::with_rotation_chances(entries, confidences, [0.25; 4])
+    }
+
+    /// Score with the given chances of a block to have each rotation (as shown on its top).
+    pub fn with_rotation_chances(
+        entries: &[RotationInfo],
+        confidences: &[f64],
+        rotation_chances: [f64; 4],
+    ) -> Self {
+        assert_eq!(entries.len(), confidences.len());
+        let steps: Vec<usize> = confidences
+            .iter()
+            .map(|&confidence| to_steps(confidence).max(1))
+            .collect();
+
+        // Distribution of the score of a random position, one block at a time
+        let mut chances = vec![1.0];
+        for block in blocks(entries) {
+            let added: [usize; 4] = std::array::from_fn(|rotation| {
+                block
+                    .iter()
+                    .filter(|&&index| !entries[index].allows_block(rotation as i32))
+                    .map(|&index| steps[index])
+                    .sum()
+            });
+            let mut next = vec![0.0; chances.len() + added.iter().max().unwrap()];
+            for (score, &chance) in chances.iter().enumerate() {
+                for (added, rotation_chance) in added.iter().zip(rotation_chances) {
+                    next[score + added] += chance * rotation_chance;
+                }
+            }
+            chances = next;
+        }
+        let cumulative = chances
+            .iter()
+            .scan(0.0, |sum, chance| {
+                *sum += chance;
+                Some(*sum)
+            })
+            .collect();
+
+        Self { steps, cumulative }
+    }
+
+    /// What a failed entry adds to the score, in steps.
+    #[inline(always)]
+    pub fn entry_steps(&self, index: usize) -> usize {
+        self.steps[index]
+    }
+
+    pub fn score(&self, failed_entries: &[usize]) -> f64 {
+        failed_entries
+            .iter()
+            .map(|&index| self.steps[index])
+            .sum::<usize>() as f64
+            / STEPS_PER_POINT
+    }
+
+    /// The chance that a random position scores as well or better.
+    pub fn p_value(&self, score: f64) -> f64 {
+        let steps = to_steps(score).min(self.cumulative.len() - 1);
+        self.cumulative[steps].min(1.0)
+    }
+}
+
+/// A score in steps, e.g. to compare the sum of [`Scoring::entry_steps`] against.
+pub fn to_steps(score: f64) -> usize {
+    (score * STEPS_PER_POINT).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation_info::Face;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn p_value_of_two_top_entries() {
+        let entries = [
+            RotationInfo::new(0, 0, 0, 1, Face::Up),
+            RotationInfo::new(1, 0, 0, 2, Face::Up),
+        ];
+        let scoring = Scoring::new(&entries, &[1.0, 0.5]);
+        assert_eq!(scoring.score(&[]), 0.0);
+        assert_eq!(scoring.score(&[0, 1]), 1.5);
+        // Both match
+        assert!(close(scoring.p_value(0.0), 1.0 / 16.0));
+        // Only the less certain one fails
+        assert!(close(scoring.p_value(0.5), 1.0 / 16.0 + 3.0 / 16.0));
+        assert!(close(scoring.p_value(1.0), 7.0 / 16.0));
+        assert!(close(scoring.p_value(1.5), 1.0));
+    }
+
+    #[test]
+    fn faces_of_one_block_are_taken_together() {
+        // The side shows whether the top rotation is odd, so it adds nothing once the top matches
+        let entries = [
+            RotationInfo::new(0, 0, 0, 1, Face::Up),
+            RotationInfo::new(0, 0, 0, 1, Face::North),
+        ];
+        let scoring = Scoring::new(&entries, &[1.0, 1.0]);
+        assert!(close(scoring.p_value(0.0), 1.0 / 4.0));
+        // Rotation 3 only fails the top
+        assert!(close(scoring.p_value(1.0), 2.0 / 4.0));
+        assert!(close(scoring.p_value(2.0), 1.0));
+
+        // A side that contradicts the top can never match along with it
+        let entries = [
+            RotationInfo::new(0, 0, 0, 1, Face::Up),
+            RotationInfo::new(0, 0, 0, 0, Face::East),
+        ];
+        let scoring = Scoring::new(&entries, &[1.0, 1.0]);
+        assert!(close(scoring.p_value(0.0), 0.0));
+        assert!(close(scoring.p_value(1.0), 3.0 / 4.0));
+
+        assert_eq!(blocks(&entries), vec![vec![0, 1]]);
+    }
+}
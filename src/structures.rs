@@ -0,0 +1,168 @@
+use crate::world::World;
+use libcubiomes_sys::{Generator, Pos, StructureConfig};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Overworld structures that can be searched near.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Structure {
+    Village,
+    Outpost,
+    AncientCity,
+    DesertPyramid,
+    JungleTemple,
+    SwampHut,
+    Igloo,
+    Monument,
+    Mansion,
+    RuinedPortal,
+    Shipwreck,
+    OceanRuin,
+}
+
+impl Structure {
+    pub const fn to_cubiomes(self) -> libcubiomes_sys::StructureType {
+        match self {
+            Structure::Village => libcubiomes_sys::StructureType_Village,
+            Structure::Outpost => libcubiomes_sys::StructureType_Outpost,
+            Structure::AncientCity => libcubiomes_sys::StructureType_Ancient_City,
+            Structure::DesertPyramid => libcubiomes_sys::StructureType_Desert_Pyramid,
+            Structure::JungleTemple => libcubiomes_sys::StructureType_Jungle_Temple,
+            Structure::SwampHut => libcubiomes_sys::StructureType_Swamp_Hut,
+            Structure::Igloo => libcubiomes_sys::StructureType_Igloo,
+            Structure::Monument => libcubiomes_sys::StructureType_Monument,
+            Structure::Mansion => libcubiomes_sys::StructureType_Mansion,
+            Structure::RuinedPortal => libcubiomes_sys::StructureType_Ruined_Portal,
+            Structure::Shipwreck => libcubiomes_sys::StructureType_Shipwreck,
+            Structure::OceanRuin => libcubiomes_sys::StructureType_Ocean_Ruin,
+        }
+    }
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Structure::Village => "village",
+            Structure::Outpost => "outpost",
+            Structure::AncientCity => "ancient_city",
+            Structure::DesertPyramid => "desert_pyramid",
+            Structure::JungleTemple => "jungle_temple",
+            Structure::SwampHut => "swamp_hut",
+            Structure::Igloo => "igloo",
+            Structure::Monument => "monument",
+            Structure::Mansion => "mansion",
+            Structure::RuinedPortal => "ruined_portal",
+            Structure::Shipwreck => "shipwreck",
+            Structure::OceanRuin => "ocean_ruin",
+        })
+    }
+}
+
+/// Only scan columns within `distance` blocks (horizontally) of a structure.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+pub struct NearStructure {
+    pub structure: Structure,
+    pub distance: i32,
+}
+
+/// Limits the scan to columns near any of the configured structures.
+pub struct StructureFilter {
+    world: World,
+    generator: Box<Generator>,
+    near: Vec<(NearStructure, StructureConfig)>,
+    /// Structures near the prepared area and the distance to check around them
+    positions: Vec<(i32, i32, i32)>,
+}
+
+impl StructureFilter {
+    pub fn new(world: World, near: &[NearStructure]) -> Result<Self, String> {
+        let near = near
+            .iter()
+            .map(|&near| {
+                // Safety: StructureConfig is a plain C struct
+                let mut config: StructureConfig = unsafe { std::mem::zeroed() };
+                let ok = unsafe {
+                    libcubiomes_sys::getStructureConfig(
+                        near.structure.to_cubiomes() as _,
+                        world.version.to_cubiomes() as _,
+                        &mut config,
+                    )
+                };
+                if ok == 0 {
+                    return Err(format!(
+                        "{} doesn't generate in {}",
+                        near.structure, world.version
+                    ));
+                }
+                Ok((near, config))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            world,
+            generator: world.generator(),
+            near,
+            positions: vec![],
+        })
+    }
+
+    /// Find all structures near an area (inclusive bounds, in blocks).
+    pub fn prepare(&mut self, x_min: i32, x_max: i32, z_min: i32, z_max: i32) {
+        self.positions.clear();
+        for &(near, config) in &self.near {
+            let region_size = config.regionSize as i32 * 16;
+            let regions = |min: i32, max: i32| {
+                (min - near.distance).div_euclid(region_size)
+                    ..=(max + near.distance).div_euclid(region_size)
+            };
+            for region_x in regions(x_min, x_max) {
+                for region_z in regions(z_min, z_max) {
+                    let mut pos = Pos { x: 0, z: 0 };
+                    let found = unsafe {
+                        libcubiomes_sys::getStructurePos(
+                            near.structure.to_cubiomes() as _,
+                            self.world.version.to_cubiomes() as _,
+                            self.world.seed as u64,
+                            region_x,
+                            region_z,
+                            &mut pos,
+                        )
+                    };
+                    if found == 0
+                        || pos.x < x_min - near.distance
+                        || pos.x > x_max + near.distance
+                        || pos.z < z_min - near.distance
+                        || pos.z > z_max + near.distance
+                    {
+                        continue;
+                    }
+                    let viable = unsafe {
+                        libcubiomes_sys::isViableStructurePos(
+                            near.structure.to_cubiomes() as _,
+                            &mut *self.generator,
+                            pos.x,
+                            pos.z,
+                            0,
+                        )
+                    };
+                    if viable != 0 {
+                        self.positions.push((pos.x, pos.z, near.distance));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a column of the prepared area is near any structure.
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        self.positions.iter().any(|&(sx, sz, distance)| {
+            let (dx, dz) = ((x - sx) as i64, (z - sz) as i64);
+            dx * dx + dz * dz <= distance as i64 * distance as i64
+        })
+    }
+
+    /// Whether no structure is near the prepared area.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
@@ -0,0 +1,78 @@
+use crate::world::World;
+use libcubiomes_sys::{Generator, SurfaceNoise};
+
+/// Limits the scanned Y range of every column to a window around the approximate
+/// surface height of the world.
+pub struct SurfaceFilter {
+    generator: Box<Generator>,
+    noise: Box<SurfaceNoise>,
+    /// Blocks below and above the estimated surface that get scanned
+    window: i32,
+    /// Quart X and Z of the first height and the width of the area in quarts
+    quart_x: i32,
+    quart_z: i32,
+    width: i32,
+    heights: Vec<f32>,
+}
+
+impl SurfaceFilter {
+    pub fn new(world: World, window: i32) -> Self {
+        let generator = world.generator();
+        // Safety: SurfaceNoise is a plain C struct that gets fully initialized by initSurfaceNoise
+        let mut noise: Box<SurfaceNoise> = Box::new(unsafe { std::mem::zeroed() });
+        unsafe {
+            libcubiomes_sys::initSurfaceNoise(
+                &mut *noise,
+                world.dimension.to_cubiomes() as _,
+                world.seed as u64,
+            );
+        }
+        Self {
+            generator,
+            noise,
+            window,
+            quart_x: 0,
+            quart_z: 0,
+            width: 0,
+            heights: vec![],
+        }
+    }
+
+    /// Estimate the surface height for an area (inclusive bounds, in blocks).
+    pub fn prepare(
+        &mut self,
+        x_min: i32,
+        x_max: i32,
+        z_min: i32,
+        z_max: i32,
+    ) -> Result<(), String> {
+        self.quart_x = x_min.div_euclid(4);
+        self.quart_z = z_min.div_euclid(4);
+        self.width = x_max.div_euclid(4) - self.quart_x + 1;
+        let height = z_max.div_euclid(4) - self.quart_z + 1;
+        self.heights = vec![0.0; (self.width * height) as usize];
+        let err = unsafe {
+            libcubiomes_sys::mapApproxHeight(
+                self.heights.as_mut_ptr(),
+                std::ptr::null_mut(),
+                &*self.generator,
+                &*self.noise,
+                self.quart_x,
+                self.quart_z,
+                self.width,
+                height,
+            )
+        };
+        if err != 0 {
+            return Err(format!("Failed to estimate the surface height from X {x_min}, Z {z_min} to X {x_max}, Z {z_max}"));
+        }
+        Ok(())
+    }
+
+    /// The Y range (inclusive) to scan for a column of the prepared area.
+    pub fn y_range(&self, x: i32, z: i32) -> (i32, i32) {
+        let index = (z.div_euclid(4) - self.quart_z) * self.width + x.div_euclid(4) - self.quart_x;
+        let surface = self.heights[index as usize].floor() as i32;
+        (surface - self.window, surface + self.window)
+    }
+}
@@ -0,0 +1,41 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// A path in the temp directory that no other test uses. The file (if any) gets deleted
+/// when dropped, also when the test fails.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// A new path ending with the given extension (e.g. "json").
+    pub fn new(extension: &str) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        Self(std::env::temp_dir().join(format!(
+            "minecraft-texture-rotations-{}-{id}.{extension}",
+            std::process::id()
+        )))
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
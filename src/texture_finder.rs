@@ -1,8 +1,23 @@
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
 
-use crate::{placement::Placement, texture_provider::TextureProvider};
-use cubiomes::finders::{BiomeCache, BiomeID, CoordScaling, CubiomesFinder};
+use crate::{
+    biome_map::BiomeMapReader,
+    facing::Facing,
+    placement::Placement,
+    progress::Progress,
+    region::{Area, Rect, TILE_SAMPLES},
+    result_sink::{Hit, Results},
+    scoring,
+    structures::StructureFilter,
+    surface::SurfaceFilter,
+    texture_provider::TextureProvider,
+    top_hits::TopHits,
+    world::World,
+};
+use cubiomes::finders::{BiomeID, CubiomesFinder};
 
 pub struct TextureFinder<T> {
     pub start_x: i32,
@@ -12,214 +27,451 @@ pub struct TextureFinder<T> {
     pub z_min: i32,
     pub z_max: i32,
     pub textures: T,
-    pub biome_filter: Option<(CubiomesFinder, HashSet<BiomeID>)>,
-    pub biome_cache: Option<BiomeCache>,
-    pub biome_cache_probe_count: u32,
-    pub placement: Placement,
+    /// Only scan columns inside this area. Scans leave it out if it is just a box, as their
+    /// tiles are inside of it anyway.
+    pub area: Option<Area>,
+    /// Used to look up the biome of hits when there is no biome map
+    pub world: Option<World>,
+    /// Set up from `world` for the first hit that needs it
+    pub hit_finder: Option<CubiomesFinder>,
+    /// Needed for the biome filter and biomes of formation entries
+    pub biome_map: Option<BiomeMapReader>,
+    pub biome_filter: Option<HashSet<BiomeID>>,
+    /// Only scan columns near structures
+    pub structure_filter: Option<StructureFilter>,
+    /// Only look up biomes of positions that already match the rotations
+    pub lazy_biome_check: bool,
+    /// Only scan a window around the approximate surface of every column
+    pub surface: Option<SurfaceFilter>,
+    /// The formation turned for every facing that should be checked
+    pub placements: Arc<Vec<(Facing, Placement)>>,
+    pub results: Arc<Results>,
+    pub progress: Arc<Progress>,
+    /// Set to stop scanning at the next X column
+    pub stop: Arc<AtomicBool>,
+    /// Collect only the best partial matches here, instead of reporting every hit
+    pub top: Option<Arc<TopHits>>,
 }
 
 impl<T: TextureProvider> TextureFinder<T> {
-    pub fn get_cached_biome_at(&mut self, x: i32, z: i32) -> BiomeID {
-        let filter = self.biome_filter.as_ref().unwrap();
-        if self.biome_cache.is_none() || !self.biome_cache.as_ref().unwrap().is_in_bounds(x, 63, z)
+    pub fn get_cached_biome_at(&mut self, x: i32, y: i32, z: i32) -> Result<BiomeID, String> {
+        self.biome_map.as_mut().unwrap().get_biome_at(x, y, z)
+    }
+
+    /// Columns of the rectangle inside the area, counted like the total of the progress.
+    fn columns_in(&self, rect: &Rect) -> u64 {
+        match &self.area {
+            Some(area) => area.estimate_columns_in(rect, TILE_SAMPLES).round() as u64,
+            None => rect.columns(),
+        }
+    }
+
+    /// The Y range (inclusive) to scan for a column.
+    fn column_y_range(&self, x: i32, z: i32) -> (i32, i32) {
+        match &self.surface {
+            Some(surface) => {
+                let (min, max) = surface.y_range(x, z);
+                (min.max(self.y_min), max.min(self.y_max))
+            }
+            None => (self.y_min, self.y_max),
+        }
+    }
+
+    /// The biome of a hit. Taken from the biome map if there is one, so it is the same as
+    /// the filters saw.
+    fn hit_biome(&mut self, x: i32, y: i32, z: i32) -> Result<Option<BiomeID>, String> {
+        if let Some(biome_map) = &mut self.biome_map {
+            return biome_map.get_biome_at(x, y, z).map(Some);
+        }
+        let Some(world) = self.world else {
+            return Ok(None);
+        };
+        let finder = self.hit_finder.get_or_insert_with(|| world.finder());
+        Ok(Some(finder.get_biome_at(x, y, z)))
+    }
+
+    /// Returns true once the results don't take any more hits. The current column then
+    /// has to be scanned again when resuming.
+    fn report_hit(
+        &mut self,
+        facing: Facing,
+        placement: &Placement,
+        (x, y, z): (i32, i32, i32),
+        failed_entries: &[usize],
+        biome_id: Option<BiomeID>,
+    ) -> Result<bool, String> {
+        if self.results.limit_reached() {
+            return Ok(true);
+        }
+        let score = placement.scoring.score(failed_entries);
+        if self
+            .top
+            .as_ref()
+            .is_some_and(|top| !top.could_enter(score, (x, y, z), facing))
         {
-            // Update cache
-            let wanted_elements = 4000000; // So we get a cache of roughly 16 MiB
-            let sz = (self.z_max - self.z_min) + 1;
-            let sx = (wanted_elements / sz)
-                .max(1)
-                .min((self.end_x - self.start_x) + 1);
-
-            let thread_name = std::thread::current()
-                .name()
-                .unwrap_or("Unnamed Thread")
-                .to_owned();
-            /*log::debug!(
-                "[{thread_name}] Generating biome cache for an {sx}x{sz} area ({} blocks total, last cache had {} probes)...",
-                sx * sz, self.biome_cache_probe_count
-            );*/
-            log::debug!("[{thread_name}] Generating biome cache for an {sx}x{sz} area...",);
-            let start = Instant::now();
-            self.biome_cache = Some(BiomeCache::new(
-                &filter.0,
-                CoordScaling::Block,
-                (x, 63, self.z_min),
-                (sx, 1, sz),
-            ));
-            self.biome_cache_probe_count = 0;
-            log::debug!(
-                "[{thread_name}] Generated biome cache in {:?}",
-                start.elapsed()
-            );
+            return Ok(false);
         }
+        let biome_id = match biome_id {
+            Some(biome_id) => Some(biome_id),
+            None => self.hit_biome(x, y, z)?,
+        };
 
-        self.biome_cache_probe_count += 1;
-        self.biome_cache.as_ref().unwrap().get_biome_at(x, 64, z)
+        let hit = Hit {
+            x,
+            y,
+            z,
+            facing,
+            fails: failed_entries.len(),
+            failed_entries: failed_entries.to_vec(),
+            biome: biome_id,
+            provider: T::NAME.to_owned(),
+            score,
+            p_value: placement.scoring.p_value(score),
+        };
+        if let Some(top) = &self.top {
+            top.offer(hit);
+            return Ok(false);
+        }
+        let new = self.results.report(&hit);
+        if new {
+            self.progress.add_hit();
+        }
+        if self.results.limit_reached() {
+            self.stop.store(true, Ordering::Relaxed);
+            return Ok(true);
+        }
+        Ok(false)
     }
 
-    pub fn run(&mut self) {
+    /// Check a position in one facing, allowing failed entries up to a score of `max_steps`
+    /// (see [`scoring::to_steps`]). Scans with a tolerance and rechecks both go through here.
+    ///
+    /// `failed` gets the failed entries. `biome_id` is the biome of the position if it was
+    /// already checked against the biome filter, otherwise it is looked up here.
+    fn check_with_tolerance(
+        &mut self,
+        facing: Facing,
+        placement: &Placement,
+        (x, y, z): (i32, i32, i32),
+        max_steps: usize,
+        failed: &mut Vec<usize>,
+        biome_id: &mut Option<BiomeID>,
+    ) -> Result<bool, String> {
+        failed.clear();
+        let mut steps = 0;
+        for (index, b) in placement.entries.iter().enumerate() {
+            if !b.rotation.allows(self.textures.get_texture(
+                x + b.x,
+                y + b.y,
+                z + b.z,
+                b.face.modulo(),
+            )) {
+                steps += placement.scoring.entry_steps(index);
+                if steps > max_steps {
+                    return Ok(false);
+                }
+                failed.push(index);
+            }
+        }
+        // Biomes can only add to the score, so don't look them up for hopeless positions
+        if let Some(top) = &self.top {
+            if !top.could_enter(placement.scoring.score(failed), (x, y, z), facing) {
+                return Ok(false);
+            }
+        }
+
+        if let (Some(biome_map), Some(biome_ids), None) =
+            (&mut self.biome_map, &self.biome_filter, *biome_id)
+        {
+            let id = biome_map.get_biome_at(x, y, z)?;
+            if !biome_ids.contains(&id) {
+                return Ok(false);
+            }
+            *biome_id = Some(id);
+        }
+        let biome_fails =
+            entry_biome_fails(&self.textures, &mut self.biome_map, placement, (x, y, z))?;
+        for index in biome_fails {
+            steps += placement.scoring.entry_steps(index);
+            if steps > max_steps {
+                return Ok(false);
+            }
+            failed.push(index);
+        }
+        failed.sort_unstable();
+        Ok(true)
+    }
+
+    /// Check a single position again in the given facing, with the same filters and
+    /// counting failures the same way as [`Self::run_with_tolerance`]. Reports the position
+    /// and returns true if it matches.
+    pub fn recheck(
+        &mut self,
+        position: (i32, i32, i32),
+        facing: Facing,
+        max_failures: f64,
+    ) -> Result<bool, String> {
+        let (x, y, z) = position;
+        if self.area.as_ref().is_some_and(|area| !area.contains(x, z)) {
+            return Ok(false);
+        }
+        if let Some(structures) = &mut self.structure_filter {
+            structures.prepare(x, x, z, z);
+            if !structures.contains(x, z) {
+                return Ok(false);
+            }
+        }
+        if let Some(surface) = &mut self.surface {
+            surface.prepare(x, x, z, z)?;
+        }
+        let (y_min, y_max) = self.column_y_range(x, z);
+        if !(y_min..=y_max).contains(&y) {
+            return Ok(false);
+        }
+
+        let placements = Arc::clone(&self.placements);
+        let Some((_, placement)) = placements.iter().find(|(other, _)| *other == facing) else {
+            return Ok(false);
+        };
+        let (mut failed, mut biome_id) = (vec![], None);
+        let max_steps = scoring::to_steps(max_failures);
+        if !self.check_with_tolerance(
+            facing,
+            placement,
+            position,
+            max_steps,
+            &mut failed,
+            &mut biome_id,
+        )? {
+            return Ok(false);
+        }
+        self.report_hit(facing, placement, position, &failed, biome_id)?;
+        Ok(true)
+    }
+
+    /// Returns the X column the scan stopped at, if it was stopped early.
+    pub fn run(&mut self) -> Result<Option<i32>, String> {
         let thread_name = std::thread::current()
             .name()
             .unwrap_or("Unnamed Thread")
             .to_owned();
-        log::debug!(
-            "[{}] Will scan from X {} to {} (inclusive)",
+        log::trace!(
+            "[{}] Will scan from X {} to {} and Z {} to {} (inclusive)",
             thread_name,
             self.start_x,
             self.end_x,
+            self.z_min,
+            self.z_max,
         );
 
         let first = Instant::now();
+        let rect = Rect {
+            x_min: self.start_x,
+            x_max: self.end_x,
+            z_min: self.z_min,
+            z_max: self.z_max,
+        };
+        let check_area = self
+            .area
+            .as_ref()
+            .is_some_and(|area| !area.contains_rect(&rect));
+        if let Some(structures) = &mut self.structure_filter {
+            structures.prepare(self.start_x, self.end_x, self.z_min, self.z_max);
+            if structures.is_empty() {
+                log::trace!("[{thread_name}] No structures nearby");
+                self.progress.add_columns(self.columns_in(&rect));
+                return Ok(None);
+            }
+        }
+        if let Some(surface) = &mut self.surface {
+            surface.prepare(self.start_x, self.end_x, self.z_min, self.z_max)?;
+        }
 
+        let placements = Arc::clone(&self.placements);
         for x in self.start_x..=self.end_x {
-            if x % 1000 == 0 {
-                let max = self.end_x - self.start_x;
-                let cur = x - self.start_x;
-                log::debug!("[{}] Progress: {}%", thread_name, cur * 100 / max);
+            if self.stop.load(Ordering::Relaxed) {
+                log::trace!("[{thread_name}] Stopped before X {x}");
+                return Ok(Some(x));
             }
+            let mut columns = 0;
             for z in self.z_min..=self.z_max {
-                let biome_id = if self.biome_filter.is_some() {
-                    let biome_id = self.get_cached_biome_at(x, z);
-                    if !self.biome_filter.as_ref().unwrap().1.contains(&biome_id) {
-                        continue;
-                    }
-                    Some(biome_id)
-                } else {
-                    None
-                };
-                for mirror_xz in [false, true] {
-                    'next_attempt: for y in self.y_min..=self.y_max {
-                        for b in &self.placement.tops_and_bottoms {
-                            let rel = (
-                                if mirror_xz { -b.x } else { b.x },
-                                if mirror_xz { -b.z } else { b.z },
-                            );
-                            if b.rotation
-                                != (self.textures.get_texture(x + rel.0, y + b.y, z + rel.1, 4)
-                                    + if mirror_xz { 2 } else { 0 })
-                                    % 4
-                            {
+                if check_area && !self.area.as_ref().unwrap().contains(x, z) {
+                    continue;
+                }
+                columns += 1;
+                if self
+                    .structure_filter
+                    .as_ref()
+                    .is_some_and(|structures| !structures.contains(x, z))
+                {
+                    continue;
+                }
+                let (y_min, y_max) = self.column_y_range(x, z);
+                for y in y_min..=y_max {
+                    let biome_id = if self.biome_filter.is_some() && !self.lazy_biome_check {
+                        let biome_id = self.get_cached_biome_at(x, y, z)?;
+                        if !self.biome_filter.as_ref().unwrap().contains(&biome_id) {
+                            continue;
+                        }
+                        Some(biome_id)
+                    } else {
+                        None
+                    };
+                    'next_attempt: for (facing, placement) in placements.iter() {
+                        for b in placement.tops.iter().chain(&placement.bottoms) {
+                            if !b.rotation.allows(self.textures.get_texture(
+                                x + b.x,
+                                y + b.y,
+                                z + b.z,
+                                4,
+                            )) {
                                 continue 'next_attempt;
                             }
                         }
-                        for b in &self.placement.sides {
-                            let rel = (
-                                if mirror_xz { -b.x } else { b.x },
-                                if mirror_xz { -b.z } else { b.z },
-                            );
-                            if b.rotation
-                                != self.textures.get_texture(x + rel.0, y + b.y, z + rel.1, 2) % 4
-                            {
+                        for b in &placement.sides {
+                            if !b.rotation.allows(self.textures.get_texture(
+                                x + b.x,
+                                y + b.y,
+                                z + b.z,
+                                2,
+                            )) {
                                 continue 'next_attempt;
                             }
                         }
 
-                        let biome_id = if let Some(biome_id) = biome_id {
-                            biome_id
-                        } else {
-                            CubiomesFinder::new(
-                                crate::LO_SEED,
-                                libcubiomes_sys::MCVersion_MC_1_19,
-                                libcubiomes_sys::Dimension_DIM_OVERWORLD,
-                            )
-                            .get_biome_at(x, y, z)
+                        let biome_id = match (&mut self.biome_map, &self.biome_filter) {
+                            (Some(biome_map), Some(biome_ids)) if self.lazy_biome_check => {
+                                let biome_id = biome_map.get_biome_at(x, y, z)?;
+                                if !biome_ids.contains(&biome_id) {
+                                    continue 'next_attempt;
+                                }
+                                Some(biome_id)
+                            }
+                            _ => biome_id,
                         };
-
-                        log::info!(
-                            "[{thread_name}] Found at X: {x}, Y: {y}, Z: {z} (biome {biome_id}, mirror_xz {mirror_xz})",
-                        );
+                        let biome_fails = entry_biome_fails(
+                            &self.textures,
+                            &mut self.biome_map,
+                            placement,
+                            (x, y, z),
+                        )?;
+                        if !biome_fails.is_empty() {
+                            continue 'next_attempt;
+                        }
+                        if self.report_hit(*facing, placement, (x, y, z), &biome_fails, biome_id)? {
+                            log::trace!("[{thread_name}] Enough hits found at X {x}");
+                            return Ok(Some(x));
+                        }
                     }
                 }
             }
+            self.progress.add_columns(columns);
         }
 
-        log::debug!("[{thread_name}] Finished after {:?}", first.elapsed());
+        log::trace!("[{thread_name}] Finished after {:?}", first.elapsed());
+        Ok(None)
     }
 
-    pub fn run_with_tolerance(&mut self, max_failures: usize) {
+    /// Returns the X column the scan stopped at, if it was stopped early.
+    pub fn run_with_tolerance(&mut self, max_failures: f64) -> Result<Option<i32>, String> {
         let thread_name = std::thread::current()
             .name()
             .unwrap_or("Unnamed Thread")
             .to_owned();
-        log::debug!(
-            "[{}] Will scan from X {} to {} (inclusive). Tolerating up to {} failures.",
+        log::trace!(
+            "[{}] Will scan from X {} to {} and Z {} to {} (inclusive). Tolerating failures up to a score of {}.",
             thread_name,
             self.start_x,
             self.end_x,
+            self.z_min,
+            self.z_max,
             max_failures,
         );
 
         let first = Instant::now();
+        let rect = Rect {
+            x_min: self.start_x,
+            x_max: self.end_x,
+            z_min: self.z_min,
+            z_max: self.z_max,
+        };
+        let check_area = self
+            .area
+            .as_ref()
+            .is_some_and(|area| !area.contains_rect(&rect));
+        if let Some(structures) = &mut self.structure_filter {
+            structures.prepare(self.start_x, self.end_x, self.z_min, self.z_max);
+            if structures.is_empty() {
+                log::trace!("[{thread_name}] No structures nearby");
+                self.progress.add_columns(self.columns_in(&rect));
+                return Ok(None);
+            }
+        }
+        if let Some(surface) = &mut self.surface {
+            surface.prepare(self.start_x, self.end_x, self.z_min, self.z_max)?;
+        }
+
+        let placements = Arc::clone(&self.placements);
+        let max_steps = scoring::to_steps(max_failures);
+        let mut failed = vec![];
 
         for x in self.start_x..=self.end_x {
-            if x % 1000 == 0 {
-                let max = self.end_x - self.start_x;
-                let cur = x - self.start_x;
-                log::debug!("[{}] Progress: {}%", thread_name, cur * 100 / max);
+            if self.stop.load(Ordering::Relaxed) {
+                log::trace!("[{thread_name}] Stopped before X {x}");
+                return Ok(Some(x));
             }
+            let mut columns = 0;
             for z in self.z_min..=self.z_max {
-                let biome_id = if self.biome_filter.is_some() {
-                    let biome_id = self.get_cached_biome_at(x, z);
-                    if !self.biome_filter.as_ref().unwrap().1.contains(&biome_id) {
-                        continue;
-                    }
-                    Some(biome_id)
-                } else {
-                    None
-                };
-                for mirror_xz in [false, true] {
-                    'next_attempt: for y in self.y_min..=self.y_max {
-                        let mut fails: usize = 0;
-                        for b in &self.placement.tops_and_bottoms {
-                            let rel = (
-                                if mirror_xz { -b.x } else { b.x },
-                                if mirror_xz { -b.z } else { b.z },
-                            );
-                            if b.rotation
-                                != (self.textures.get_texture(x + rel.0, y + b.y, z + rel.1, 4)
-                                    + if mirror_xz { 2 } else { 0 })
-                                    % 4
-                            {
-                                fails += 1;
-                                if fails > max_failures {
-                                    continue 'next_attempt;
-                                }
-                            }
+                if check_area && !self.area.as_ref().unwrap().contains(x, z) {
+                    continue;
+                }
+                columns += 1;
+                if self
+                    .structure_filter
+                    .as_ref()
+                    .is_some_and(|structures| !structures.contains(x, z))
+                {
+                    continue;
+                }
+                let (y_min, y_max) = self.column_y_range(x, z);
+                for y in y_min..=y_max {
+                    let biome_id = if self.biome_filter.is_some() && !self.lazy_biome_check {
+                        let biome_id = self.get_cached_biome_at(x, y, z)?;
+                        if !self.biome_filter.as_ref().unwrap().contains(&biome_id) {
+                            continue;
                         }
-                        for b in &self.placement.sides {
-                            let rel = (
-                                if mirror_xz { -b.x } else { b.x },
-                                if mirror_xz { -b.z } else { b.z },
-                            );
-                            if b.rotation
-                                != self.textures.get_texture(x + rel.0, y + b.y, z + rel.1, 2) % 4
-                            {
-                                fails += 1;
-                                if fails > max_failures {
-                                    continue 'next_attempt;
-                                }
-                            }
+                        Some(biome_id)
+                    } else {
+                        None
+                    };
+                    let max_steps = match &self.top {
+                        Some(top) => scoring::to_steps(top.max_score()),
+                        None => max_steps,
+                    };
+                    for (facing, placement) in placements.iter() {
+                        let mut biome_id = biome_id;
+                        if !self.check_with_tolerance(
+                            *facing,
+                            placement,
+                            (x, y, z),
+                            max_steps,
+                            &mut failed,
+                            &mut biome_id,
+                        )? {
+                            continue;
+                        }
+                        if self.report_hit(*facing, placement, (x, y, z), &failed, biome_id)? {
+                            log::trace!("[{thread_name}] Enough hits found at X {x}");
+                            return Ok(Some(x));
                         }
-
-                        let biome_id = if let Some(biome_id) = biome_id {
-                            biome_id
-                        } else {
-                            CubiomesFinder::new(
-                                crate::LO_SEED,
-                                libcubiomes_sys::MCVersion_MC_1_19,
-                                libcubiomes_sys::Dimension_DIM_OVERWORLD,
-                            )
-                            .get_biome_at(x, y, z)
-                        };
-                        log::info!(
-                            "[{thread_name}] Found at X: {x}, Y: {y}, Z: {z} ({fails} fails, biome {biome_id}, mirror_xz {mirror_xz})",
-                        );
                     }
                 }
             }
+            self.progress.add_columns(columns);
         }
 
-        log::debug!("[{thread_name}] Finished after {:?}", first.elapsed());
+        log::trace!("[{thread_name}] Finished after {:?}", first.elapsed());
+        Ok(None)
     }
     /*
     fn get_rotations_for_range(
@@ -232,6 +484,38 @@ impl<T: TextureProvider> TextureFinder<T> {
     ) -> RotationCache {
     }*/
 }
+
+/// Indices of entries that match their rotation, but are outside of their allowed biomes.
+fn entry_biome_fails<T: TextureProvider>(
+    textures: &T,
+    biome_map: &mut Option<BiomeMapReader>,
+    placement: &Placement,
+    (x, y, z): (i32, i32, i32),
+) -> Result<Vec<usize>, String> {
+    let mut fails = vec![];
+    let Some(biome_map) = biome_map else {
+        return Ok(fails);
+    };
+    for (index, (b, biomes)) in placement
+        .entries
+        .iter()
+        .zip(&placement.entry_biomes)
+        .enumerate()
+    {
+        let Some(biomes) = biomes else {
+            continue;
+        };
+        let (bx, by, bz) = (x + b.x, y + b.y, z + b.z);
+        if b.rotation
+            .allows(textures.get_texture(bx, by, bz, b.face.modulo()))
+            && !biomes.contains(&biome_map.get_biome_at(bx, by, bz)?)
+        {
+            fails.push(index);
+        }
+    }
+    Ok(fails)
+}
+
 /*
 struct RotationCache {
     cache: Vec<u8>,
@@ -254,3 +538,166 @@ impl RotationCache {
         self.cache[self.index_of(x, y, z)]
     }
 }*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        biome_map::BiomeMap,
+        region::Region,
+        result_sink::CollectSink,
+        rotation_info::{Face, RotationInfo},
+        texture_provider::GradientTextures,
+        world::{Dimension, McVersion},
+    };
+    use std::sync::Mutex;
+
+    const POSITION: (i32, i32, i32) = (10, 50, -4);
+
+    /// A formation of three entries on two blocks that matches at [`POSITION`] facing
+    /// north, except for the entries that are given a wrong rotation.
+    fn formation(wrong: &[usize]) -> Vec<RotationInfo> {
+        let (x, y, z) = POSITION;
+        [
+            (0, 0, 0, Face::Up),
+            (1, 0, 0, Face::Up),
+            (0, 0, 0, Face::North),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(index, &(dx, dy, dz, face))| {
+            let rotation = GradientTextures.get_texture(x + dx, y + dy, z + dz, face.modulo());
+            let rotation = if wrong.contains(&index) {
+                (rotation + 1) % face.modulo()
+            } else {
+                rotation
+            };
+            RotationInfo::new(dx, dy, dz, rotation, face)
+        })
+        .collect()
+    }
+
+    fn new_finder(
+        formation: &[RotationInfo],
+        area: Option<Area>,
+    ) -> (TextureFinder<GradientTextures>, Arc<Mutex<Vec<Hit>>>) {
+        let hits = Arc::new(Mutex::new(vec![]));
+        let finder = TextureFinder {
+            start_x: 0,
+            end_x: 0,
+            y_min: 0,
+            y_max: 100,
+            z_min: 0,
+            z_max: 0,
+            textures: GradientTextures,
+            area,
+            world: None,
+            hit_finder: None,
+            biome_map: None,
+            biome_filter: None,
+            structure_filter: None,
+            lazy_biome_check: true,
+            surface: None,
+            placements: Arc::new(
+                Facing::ALL
+                    .iter()
+                    .map(|&facing| (facing, Placement::new(formation).turned(facing)))
+                    .collect(),
+            ),
+            results: Arc::new(Results::new(
+                vec![Box::new(CollectSink(Arc::clone(&hits)))],
+                HashSet::new(),
+            )),
+            progress: Arc::new(Progress::new(0, 0)),
+            stop: Arc::new(AtomicBool::new(false)),
+            top: None,
+        };
+        (finder, hits)
+    }
+
+    #[test]
+    fn recheck_counts_failures_like_a_scan() {
+        let (mut finder, hits) = new_finder(&formation(&[]), None);
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.0), Ok(true));
+        assert_eq!(hits.lock().unwrap()[0].fails, 0);
+
+        let (mut finder, hits) = new_finder(&formation(&[1]), None);
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.0), Ok(false));
+        assert_eq!(finder.recheck(POSITION, Facing::North, 1.0), Ok(true));
+        let hits = hits.lock().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].failed_entries, vec![1]);
+        assert_eq!(hits[0].score, 1.0);
+        assert_eq!((hits[0].x, hits[0].y, hits[0].z), POSITION);
+
+        // Both entries of the first block fail
+        let (mut finder, _) = new_finder(&formation(&[0, 2]), None);
+        assert_eq!(finder.recheck(POSITION, Facing::North, 1.0), Ok(false));
+        assert_eq!(finder.recheck(POSITION, Facing::North, 2.0), Ok(true));
+
+        // A failed entry counts with its confidence
+        let formation = formation(&[1]);
+        let (mut finder, hits) = new_finder(&formation, None);
+        let placement = Placement::new(&formation).with_entry_confidences(&[1.0, 0.5, 1.0]);
+        finder.placements = Arc::new(vec![(Facing::North, placement)]);
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.4), Ok(false));
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.5), Ok(true));
+        assert_eq!(hits.lock().unwrap()[0].score, 0.5);
+    }
+
+    #[test]
+    fn recheck_applies_the_filters_of_a_scan() {
+        let (x, y, z) = POSITION;
+        let area = |x_min| Area {
+            regions: vec![Region::Box {
+                x_min,
+                x_max: x_min + 100,
+                z_min: -100,
+                z_max: 100,
+            }],
+            exclude: vec![],
+        };
+        let (mut finder, _) = new_finder(&formation(&[]), Some(area(x - 50)));
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.0), Ok(true));
+        let (mut finder, hits) = new_finder(&formation(&[]), Some(area(x + 1)));
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.0), Ok(false));
+        assert!(hits.lock().unwrap().is_empty());
+
+        let (mut finder, _) = new_finder(&formation(&[]), None);
+        finder.y_max = y - 1;
+        assert_eq!(finder.recheck(POSITION, Facing::North, 0.0), Ok(false));
+        assert_eq!(finder.recheck((x, y - 1, z), Facing::North, 3.0), Ok(true));
+    }
+
+    #[test]
+    fn only_matching_entries_fail_their_biome() {
+        // No biome is allowed, so every entry that is checked fails
+        let no_biome = || Some(HashSet::new());
+        let placement = Placement::new(&formation(&[1])).with_entry_biomes(vec![
+            no_biome(),
+            no_biome(),
+            no_biome(),
+        ]);
+        let world = World {
+            seed: 1,
+            version: McVersion::V1_19,
+            dimension: Dimension::Overworld,
+        };
+        let mut biome_map = Some(BiomeMapReader::new(Arc::new(BiomeMap::new(world, 1, None))));
+        // The second entry already fails its rotation, so its biome doesn't count
+        assert_eq!(
+            entry_biome_fails(&GradientTextures, &mut biome_map, &placement, POSITION),
+            Ok(vec![0, 2])
+        );
+        let placement = placement.with_entry_biomes(vec![None, no_biome(), no_biome()]);
+        assert_eq!(
+            entry_biome_fails(&GradientTextures, &mut biome_map, &placement, POSITION),
+            Ok(vec![2])
+        );
+        // Without a biome map there is nothing to check
+        assert_eq!(
+            entry_biome_fails(&GradientTextures, &mut None, &placement, POSITION),
+            Ok(vec![])
+        );
+    }
+}
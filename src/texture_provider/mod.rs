@@ -7,6 +7,9 @@ pub use sodium19::Sodium19Textures;
 pub use vanilla::VanillaTextures;
 
 pub trait TextureProvider: Copy + Default {
+    /// Name of the provider as used in the config
+    const NAME: &'static str;
+
     fn get_coordinate_random(&self, x: i32, y: i32, z: i32) -> i64 {
         let mut l: i64 = (x * 3129871) as i64 ^ (z as i64 * 116129781i64) ^ y as i64;
         l = l * l * 42317861i64 + l * 11i64;
@@ -20,3 +23,22 @@ pub trait TextureProvider: Copy + Default {
 
     fn random(&self, seed: i64) -> i32;
 }
+
+/// A provider for tests whose top rotations go around along every axis, so neighbouring
+/// blocks differ.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GradientTextures;
+
+#[cfg(test)]
+impl TextureProvider for GradientTextures {
+    const NAME: &'static str = "Gradient";
+
+    fn get_texture(&self, x: i32, y: i32, z: i32, modulo: i32) -> i32 {
+        (x + 2 * z + 3 * y).rem_euclid(4) % modulo
+    }
+
+    fn random(&self, _seed: i64) -> i32 {
+        unreachable!()
+    }
+}
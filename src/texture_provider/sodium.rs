@@ -11,6 +11,8 @@ const PHI: i64 = 0x9E3779B97F4A7C15u64 as i64;
 pub struct SodiumTextures {}
 
 impl super::TextureProvider for SodiumTextures {
+    const NAME: &'static str = "Sodium";
+
     fn random(&self, mut seed: i64) -> i32 {
         seed ^= ((seed as u64) >> 33) as i64;
         seed *= 0xff51afd7ed558ccdu64 as i64;
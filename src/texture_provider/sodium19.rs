@@ -2,6 +2,8 @@
 pub struct Sodium19Textures {}
 
 impl super::TextureProvider for Sodium19Textures {
+    const NAME: &'static str = "Sodium19";
+
     fn random(&self, seed: i64) -> i32 {
         let mut l: i64 = seed ^ 7640891576956012809i64;
         let mut m: i64 = l + -7046029254386353131i64;
@@ -5,6 +5,8 @@ const MASK: i64 = (1i64 << 48) - 1;
 pub struct VanillaTextures {}
 
 impl super::TextureProvider for VanillaTextures {
+    const NAME: &'static str = "Vanilla";
+
     fn random(&self, seed: i64) -> i32 {
         let seed = (seed ^ MULTIPLIER) & MASK;
         ((seed * 0xBB20B4600A69i64 + 0x40942DE6BAi64) >> 16) as i32
@@ -0,0 +1,119 @@
+use crate::{facing::Facing, result_sink::Hit};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+/// The best partial matches of a scan, ranked by their score.
+///
+/// Once `k` hits are collected, a position can't score worse than the worst of them to
+/// still get in. Workers use that as a moving cutoff to give up early.
+pub struct TopHits {
+    k: usize,
+    /// Sorted from best to worst
+    hits: Mutex<Vec<Hit>>,
+    /// The cutoff until `k` hits are collected
+    max_score: f64,
+    /// Score of the worst kept hit once `k` are collected (bits of an f64)
+    worst_score: AtomicU64,
+}
+
+/// Hits with the same score are ordered by position, so the result doesn't depend on
+/// which worker found them first.
+fn rank(hit: &Hit) -> (f64, i32, i32, i32, u8) {
+    rank_of(hit.score, (hit.x, hit.y, hit.z), hit.facing)
+}
+
+fn rank_of(score: f64, (x, y, z): (i32, i32, i32), facing: Facing) -> (f64, i32, i32, i32, u8) {
+    (score, x, z, y, facing as u8)
+}
+
+impl TopHits {
+    /// `max_score` is the cutoff until `k` hits are collected.
+    pub fn new(k: usize, max_score: f64) -> Self {
+        assert!(k > 0);
+        Self {
+            k,
+            hits: Mutex::new(Vec::with_capacity(k + 1)),
+            max_score,
+            worst_score: AtomicU64::new(f64::INFINITY.to_bits()),
+        }
+    }
+
+    /// The worst score a position can have to still be one of the best. Ties with the worst
+    /// kept hit only get in if [`Self::could_enter`] says so.
+    pub fn max_score(&self) -> f64 {
+        f64::from_bits(self.worst_score.load(Ordering::Relaxed)).min(self.max_score)
+    }
+
+    /// Whether a position with this score could still be one of the best. Only needs the
+    /// lock for ties with the worst kept hit, so it can be asked before building a hit.
+    pub fn could_enter(&self, score: f64, position: (i32, i32, i32), facing: Facing) -> bool {
+        let worst_score = f64::from_bits(self.worst_score.load(Ordering::Relaxed));
+        if score != worst_score {
+            return score < worst_score;
+        }
+        let hits = self.hits.lock().unwrap();
+        hits.len() < self.k || rank_of(score, position, facing) < rank(hits.last().unwrap())
+    }
+
+    /// Keep the hit if it is one of the best so far. Returns whether it was kept.
+    pub fn offer(&self, hit: Hit) -> bool {
+        let mut hits = self.hits.lock().unwrap();
+        if hits.len() == self.k && rank(&hit) >= rank(hits.last().unwrap()) {
+            return false;
+        }
+        if hits.contains(&hit) {
+            return false;
+        }
+        let index = hits.partition_point(|other| rank(other) < rank(&hit));
+        hits.insert(index, hit);
+        hits.truncate(self.k);
+        if hits.len() == self.k {
+            let worst_score = hits.last().unwrap().score;
+            self.worst_score
+                .store(worst_score.to_bits(), Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// The best hits, from best to worst.
+    pub fn hits(&self) -> Vec<Hit> {
+        self.hits.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facing::Facing;
+
+    fn hit(x: i32, fails: usize) -> Hit {
+        Hit {
+            fails,
+            failed_entries: (0..fails).collect(),
+            score: fails as f64,
+            ..Hit::for_test(x, 0, 0, Facing::North)
+        }
+    }
+
+    #[test]
+    fn keeps_the_best_and_lowers_the_cutoff() {
+        let top = TopHits::new(2, 5.0);
+        assert!(top.offer(hit(0, 4)));
+        assert_eq!(top.max_score(), 5.0);
+        assert!(top.offer(hit(1, 3)));
+        assert_eq!(top.max_score(), 4.0);
+        assert!(top.offer(hit(2, 1)));
+        assert_eq!(top.max_score(), 3.0);
+        // Same failures, but ranked behind by position
+        assert!(!top.offer(hit(3, 3)));
+        assert!(!top.offer(hit(2, 1)));
+        assert!(top.could_enter(3.0, (-1, 0, 0), Facing::North));
+        assert!(!top.could_enter(3.0, (2, 0, 0), Facing::North));
+        assert!(!top.could_enter(3.5, (-9, 0, 0), Facing::North));
+        assert!(top.offer(hit(-1, 3)));
+        let hits: Vec<_> = top.hits().iter().map(|hit| (hit.x, hit.fails)).collect();
+        assert_eq!(hits, vec![(2, 1), (-1, 3)]);
+    }
+}
@@ -0,0 +1,171 @@
+use cubiomes::finders::CubiomesFinder;
+use libcubiomes_sys::Generator;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Minecraft versions supported for biome generation.
+#[derive(
+    Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Deserialize, Serialize,
+)]
+pub enum McVersion {
+    #[serde(rename = "1.7")]
+    V1_7,
+    #[serde(rename = "1.8")]
+    V1_8,
+    #[serde(rename = "1.9")]
+    V1_9,
+    #[serde(rename = "1.10")]
+    V1_10,
+    #[serde(rename = "1.11")]
+    V1_11,
+    #[serde(rename = "1.12")]
+    V1_12,
+    #[serde(rename = "1.13")]
+    V1_13,
+    #[serde(rename = "1.14")]
+    V1_14,
+    #[serde(rename = "1.15")]
+    V1_15,
+    #[serde(rename = "1.16")]
+    V1_16,
+    #[serde(rename = "1.17")]
+    V1_17,
+    #[serde(rename = "1.18")]
+    V1_18,
+    #[serde(rename = "1.19")]
+    #[default]
+    V1_19,
+}
+
+impl McVersion {
+    pub const fn to_cubiomes(self) -> libcubiomes_sys::MCVersion {
+        match self {
+            McVersion::V1_7 => libcubiomes_sys::MCVersion_MC_1_7,
+            McVersion::V1_8 => libcubiomes_sys::MCVersion_MC_1_8,
+            McVersion::V1_9 => libcubiomes_sys::MCVersion_MC_1_9,
+            McVersion::V1_10 => libcubiomes_sys::MCVersion_MC_1_10,
+            McVersion::V1_11 => libcubiomes_sys::MCVersion_MC_1_11,
+            McVersion::V1_12 => libcubiomes_sys::MCVersion_MC_1_12,
+            McVersion::V1_13 => libcubiomes_sys::MCVersion_MC_1_13,
+            McVersion::V1_14 => libcubiomes_sys::MCVersion_MC_1_14,
+            McVersion::V1_15 => libcubiomes_sys::MCVersion_MC_1_15,
+            McVersion::V1_16 => libcubiomes_sys::MCVersion_MC_1_16,
+            McVersion::V1_17 => libcubiomes_sys::MCVersion_MC_1_17,
+            McVersion::V1_18 => libcubiomes_sys::MCVersion_MC_1_18,
+            McVersion::V1_19 => libcubiomes_sys::MCVersion_MC_1_19,
+        }
+    }
+}
+
+impl fmt::Display for McVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minor = *self as u8 + 7;
+        write!(f, "1.{minor}")
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dimension {
+    #[default]
+    Overworld,
+    Nether,
+    End,
+}
+
+impl Dimension {
+    pub const fn to_cubiomes(self) -> libcubiomes_sys::Dimension {
+        match self {
+            Dimension::Overworld => libcubiomes_sys::Dimension_DIM_OVERWORLD,
+            Dimension::Nether => libcubiomes_sys::Dimension_DIM_NETHER,
+            Dimension::End => libcubiomes_sys::Dimension_DIM_END,
+        }
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dimension::Overworld => "overworld",
+            Dimension::Nether => "nether",
+            Dimension::End => "end",
+        })
+    }
+}
+
+/// The world biomes get generated for.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct World {
+    pub seed: i64,
+    pub version: McVersion,
+    pub dimension: Dimension,
+}
+
+impl World {
+    /// Whether biomes change with the height (at a resolution of 4 blocks).
+    pub fn has_3d_biomes(&self) -> bool {
+        match self.dimension {
+            Dimension::Overworld => self.version >= McVersion::V1_18,
+            Dimension::Nether => self.version >= McVersion::V1_16,
+            Dimension::End => false,
+        }
+    }
+
+    /// A cubiomes generator set up for this world.
+    pub fn generator(&self) -> Box<Generator> {
+        // Safety: Generator is a plain C struct that gets fully initialized by setupGenerator
+        let mut generator: Box<Generator> = Box::new(unsafe { std::mem::zeroed() });
+        unsafe {
+            libcubiomes_sys::setupGenerator(&mut *generator, self.version.to_cubiomes() as _, 0);
+            libcubiomes_sys::applySeed(
+                &mut *generator,
+                self.dimension.to_cubiomes() as _,
+                self.seed as u64,
+            );
+        }
+        generator
+    }
+
+    pub fn finder(&self) -> CubiomesFinder {
+        CubiomesFinder::new(
+            self.seed,
+            self.version.to_cubiomes(),
+            self.dimension.to_cubiomes(),
+        )
+    }
+}
+
+/// Lowest and highest block Y (inclusive) of a dimension in the given version.
+pub const fn build_limits(version: McVersion, dimension: Dimension) -> (i32, i32) {
+    match dimension {
+        Dimension::Overworld if (version as u8) >= (McVersion::V1_18 as u8) => (-64, 319),
+        Dimension::Overworld | Dimension::End => (0, 255),
+        Dimension::Nether => (0, 127),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_limits_per_version_and_dimension() {
+        // The overworld grew in both directions with 1.18
+        assert_eq!(
+            build_limits(McVersion::V1_17, Dimension::Overworld),
+            (0, 255)
+        );
+        assert_eq!(
+            build_limits(McVersion::V1_18, Dimension::Overworld),
+            (-64, 319)
+        );
+        assert_eq!(
+            build_limits(McVersion::V1_19, Dimension::Overworld),
+            (-64, 319)
+        );
+        for version in [McVersion::V1_7, McVersion::V1_19] {
+            assert_eq!(build_limits(version, Dimension::Nether), (0, 127));
+            assert_eq!(build_limits(version, Dimension::End), (0, 255));
+        }
+    }
+}